@@ -1,31 +1,38 @@
 use std::ops::{Index, IndexMut};
 
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::grapheme::{self, Grapheme};
 use crate::style::Style;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Cell {
-    pub c: char,
+    /// What is shown in the cell. A wide grapheme is followed by
+    /// [`Grapheme::CONTINUATION`] cells for the extra columns it covers.
+    pub symbol: Grapheme,
     pub style: Style,
 }
 
 impl Cell {
-    fn char(c: char) -> Self {
+    pub fn char(c: char) -> Self {
         Self {
-            c,
+            symbol: Grapheme::from_char(c),
             style: Style::default(),
         }
     }
+
+    pub fn width(&self) -> usize {
+        self.symbol.width()
+    }
 }
 
 impl Default for Cell {
     fn default() -> Self {
-        Self {
-            c: ' ',
-            style: Style::default(),
-        }
+        Self::char(' ')
     }
 }
 
+#[derive(Debug)]
 pub struct Buffer {
     data: Box<[Cell]>,
 
@@ -35,6 +42,29 @@ pub struct Buffer {
     cursor: Option<(usize, usize)>,
 }
 
+impl Clone for Buffer {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            width: self.width,
+            height: self.height,
+            cursor: self.cursor,
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        if self.data.len() == source.data.len() {
+            self.data.copy_from_slice(&source.data);
+        } else {
+            self.data = source.data.clone();
+        }
+
+        self.width = source.width;
+        self.height = source.height;
+        self.cursor = source.cursor;
+    }
+}
+
 impl Buffer {
     pub fn new(width: usize, height: usize) -> Self {
         Self::new_inner(width, height, vec![], None)
@@ -63,7 +93,7 @@ impl Buffer {
         let len = width
             .checked_mul(height)
             .expect("width * height overflowed");
-        data.extend(std::iter::repeat(Cell::default()).take(len));
+        data.extend(std::iter::repeat_n(Cell::default(), len));
 
         Self {
             data: data.into_boxed_slice(),
@@ -103,10 +133,28 @@ impl Buffer {
         self.data.get_mut(index)
     }
 
+    /// Copy another buffer into this one at a position, cutting it off at
+    /// the edges.
+    ///
+    /// Graphemes are put like with [`set_grapheme`](Self::set_grapheme), so
+    /// a wide one that is cut off or partly covered is replaced with spaces.
     pub fn blit(&mut self, x: usize, y: usize, buf: &Buffer, set_cursor: bool) {
-        for (x, buf_x) in (x..self.width).zip(0..buf.width) {
-            for (y, buf_y) in (y..self.height).zip(0..buf.height) {
-                self[[x, y]] = buf[[buf_x, buf_y]];
+        for (y, buf_y) in (y..self.height).zip(0..buf.height) {
+            for (x, buf_x) in (x..self.width).zip(0..buf.width) {
+                let cell = buf[[buf_x, buf_y]];
+
+                // Continuations are put along with their grapheme, unless
+                // it is missing.
+                let symbol = match cell.symbol.is_continuation() {
+                    true if buf_x > 0 => continue,
+                    true => " ",
+                    false => cell.symbol.as_str(),
+                };
+
+                let width = self.set_grapheme(x, y, symbol);
+                for x in x..(x + width) {
+                    self[[x, y]].style = cell.style;
+                }
             }
         }
 
@@ -117,6 +165,66 @@ impl Buffer {
         }
     }
 
+    /// Put a grapheme in a cell, along with the continuation cells it
+    /// needs, keeping their style. Returns how many columns it covers.
+    ///
+    /// Any wide grapheme that is partly overwritten is replaced with spaces,
+    /// and one that doesn't fit before the edge is replaced with a space.
+    pub fn set_grapheme(&mut self, x: usize, y: usize, grapheme: &str) -> usize {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+
+        let mut symbol = Grapheme::new(grapheme);
+        let mut width = grapheme::width(grapheme);
+        if x + width > self.width {
+            symbol = Grapheme::from_char(' ');
+            width = 1;
+        }
+
+        // the start of a wide grapheme that is being overwritten
+        if self[[x, y]].symbol.is_continuation() {
+            if let Some(start) = (0..x)
+                .rev()
+                .find(|&x| !self[[x, y]].symbol.is_continuation())
+            {
+                self.clear_cells(start..x, y);
+            }
+        }
+
+        self[[x, y]].symbol = symbol;
+        for x in (x + 1)..(x + width) {
+            self[[x, y]].symbol = Grapheme::CONTINUATION;
+        }
+
+        // the rest of a wide grapheme that is being overwritten
+        let end = (x + width..self.width)
+            .find(|&x| !self[[x, y]].symbol.is_continuation())
+            .unwrap_or(self.width);
+        self.clear_cells(x + width..end, y);
+
+        width
+    }
+
+    /// Put a string's graphemes in a row, stopping at the edge. Returns the
+    /// column after the last one.
+    pub fn set_str(&mut self, mut x: usize, y: usize, s: &str) -> usize {
+        for grapheme in s.graphemes(true) {
+            if x >= self.width {
+                break;
+            }
+            x += self.set_grapheme(x, y, grapheme);
+        }
+
+        x.min(self.width)
+    }
+
+    fn clear_cells(&mut self, xs: std::ops::Range<usize>, y: usize) {
+        for x in xs {
+            self[[x, y]].symbol = Grapheme::from_char(' ');
+        }
+    }
+
     pub fn set_cursor(&mut self, cursor: Option<(usize, usize)>) {
         self.cursor = cursor;
     }
@@ -165,8 +273,44 @@ mod tests {
         arr[[0, 0]] = b;
         arr[[9, 9]] = c;
 
-        assert_eq!(arr[[0, 0]].c, 'b');
-        assert_eq!(arr[[9, 9]].c, 'c');
+        assert_eq!(arr[[0, 0]].symbol.as_str(), "b");
+        assert_eq!(arr[[9, 9]].symbol.as_str(), "c");
         assert!(arr.get(10, 10).is_none());
     }
+
+    #[test]
+    fn wide_graphemes() {
+        let mut arr = Buffer::new(10, 10);
+
+        let symbols = |arr: &Buffer| -> Vec<String> {
+            (0..4)
+                .map(|x| format!("{:?}", arr[[x, 0]].symbol))
+                .collect()
+        };
+
+        // a wide grapheme that doesn't fit is replaced
+        assert_eq!(arr.set_str(0, 0, "a漢"), 3);
+        assert_eq!(arr.set_str(7, 1, "漢漢"), 10);
+        assert_eq!(arr[[9, 1]].symbol.as_str(), " ");
+        assert_eq!(symbols(&arr), ["\"a\"", "\"漢\"", "Continuation", "\" \""]);
+
+        // overwriting either half of a wide grapheme clears the other
+        arr.set_grapheme(2, 0, "x");
+        assert_eq!(symbols(&arr), ["\"a\"", "\" \"", "\"x\"", "\" \""]);
+        arr.set_grapheme(1, 0, "漢");
+        arr.set_grapheme(1, 0, "y");
+        assert_eq!(symbols(&arr), ["\"a\"", "\"y\"", "\" \"", "\" \""]);
+
+        // a wide grapheme that is partly covered by a blit is replaced, as
+        // is one that the edge cuts off
+        let mut src = Buffer::new(3, 1);
+        src.set_str(0, 0, "b漢");
+        arr.set_str(0, 0, "漢漢");
+        arr.blit(1, 0, &src, false);
+        assert_eq!(symbols(&arr), ["\" \"", "\"b\"", "\"漢\"", "Continuation"]);
+
+        let mut arr = Buffer::new(4, 1);
+        arr.blit(2, 0, &src, false);
+        assert_eq!(symbols(&arr), ["\" \"", "\" \"", "\"b\"", "\" \""]);
+    }
 }
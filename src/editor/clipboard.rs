@@ -0,0 +1,316 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::register::{Register, RegisterKind};
+use crate::term::{self, ClipboardKind};
+
+/// The programs the `+` and `*` registers copy and paste with. Each is a
+/// program followed by its arguments; copying writes the text to its
+/// stdin, and pasting reads it from its stdout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardCommands {
+    pub copy: Vec<String>,
+    pub paste: Vec<String>,
+    pub copy_primary: Vec<String>,
+    pub paste_primary: Vec<String>,
+}
+
+impl ClipboardCommands {
+    fn new(copy: &[&str], paste: &[&str], copy_primary: &[&str], paste_primary: &[&str]) -> Self {
+        let to_vec = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+        Self {
+            copy: to_vec(copy),
+            paste: to_vec(paste),
+            copy_primary: to_vec(copy_primary),
+            paste_primary: to_vec(paste_primary),
+        }
+    }
+
+    /// The first of the usual clipboard programs that is installed, for the
+    /// display server in use.
+    pub fn detect() -> Option<Self> {
+        let has_env = |name| std::env::var_os(name).is_some();
+
+        if has_env("WAYLAND_DISPLAY") && in_path("wl-copy") && in_path("wl-paste") {
+            return Some(Self::new(
+                &["wl-copy", "--type", "text/plain"],
+                &["wl-paste", "--no-newline"],
+                &["wl-copy", "--primary", "--type", "text/plain"],
+                &["wl-paste", "--no-newline", "--primary"],
+            ));
+        }
+
+        if has_env("DISPLAY") && in_path("xclip") {
+            return Some(Self::new(
+                &["xclip", "-in", "-selection", "clipboard"],
+                &["xclip", "-out", "-selection", "clipboard"],
+                &["xclip", "-in", "-selection", "primary"],
+                &["xclip", "-out", "-selection", "primary"],
+            ));
+        }
+
+        if has_env("DISPLAY") && in_path("xsel") {
+            return Some(Self::new(
+                &["xsel", "--input", "--clipboard"],
+                &["xsel", "--output", "--clipboard"],
+                &["xsel", "--input", "--primary"],
+                &["xsel", "--output", "--primary"],
+            ));
+        }
+
+        // macOS has no primary selection.
+        if in_path("pbcopy") && in_path("pbpaste") {
+            return Some(Self::new(
+                &["pbcopy"],
+                &["pbpaste"],
+                &["pbcopy"],
+                &["pbpaste"],
+            ));
+        }
+
+        None
+    }
+
+    fn copy(&self, kind: ClipboardKind) -> &[String] {
+        match kind {
+            ClipboardKind::Clipboard => &self.copy,
+            ClipboardKind::Primary => &self.copy_primary,
+        }
+    }
+
+    fn paste(&self, kind: ClipboardKind) -> &[String] {
+        match kind {
+            ClipboardKind::Clipboard => &self.paste,
+            ClipboardKind::Primary => &self.paste_primary,
+        }
+    }
+}
+
+/// How long a clipboard command has to finish, so that one whose
+/// clipboard owner doesn't answer can't freeze the editor.
+const COMMAND_TIMEOUT: Duration = Duration::from_millis(500);
+
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|dir| Path::new(&dir).join(program).is_file())
+    })
+}
+
+/// The system clipboard, behind the `+` and `*` registers.
+///
+/// Text is copied both with OSC 52, through the terminal, and with the
+/// clipboard commands. As OSC 52 can't be read back, and either may not
+/// work, a copy is also kept here to paste when the commands can't.
+#[derive(Debug, Default)]
+pub struct Clipboard {
+    osc52: bool,
+    commands: Option<ClipboardCommands>,
+
+    clipboard: Option<Register>,
+    primary: Option<Register>,
+}
+
+impl Clipboard {
+    pub fn new(osc52: bool, commands: Option<ClipboardCommands>) -> Self {
+        Self {
+            osc52,
+            commands,
+            ..Default::default()
+        }
+    }
+
+    pub fn get(&self, kind: ClipboardKind) -> Option<Register> {
+        let internal = self.internal(kind);
+
+        let pasted = self
+            .commands
+            .as_ref()
+            .and_then(|commands| paste(commands.paste(kind)).ok())
+            .filter(|text| !text.is_empty());
+
+        match pasted {
+            // The clipboard only holds text, so remember how it was yanked
+            // if it came from here.
+            Some(text) if internal.is_some_and(|register| register.text == text) => {
+                internal.cloned()
+            }
+            Some(text) => {
+                let kind = match text.ends_with('\n') {
+                    true => RegisterKind::Linewise,
+                    false => RegisterKind::Charwise,
+                };
+                Some(Register::new(text, kind))
+            }
+            None => internal.cloned(),
+        }
+    }
+
+    /// What was last copied from here, without asking the system
+    /// clipboard.
+    pub fn internal(&self, kind: ClipboardKind) -> Option<&Register> {
+        match kind {
+            ClipboardKind::Clipboard => self.clipboard.as_ref(),
+            ClipboardKind::Primary => self.primary.as_ref(),
+        }
+    }
+
+    /// Copy to the system clipboard in every way that might work. Failing
+    /// isn't an error, as the text can still be pasted within the editor.
+    pub fn set(&mut self, kind: ClipboardKind, register: Register) {
+        if self.osc52 {
+            let _ = term::set_clipboard(kind, &register.text);
+        }
+
+        if let Some(commands) = &self.commands {
+            let _ = copy(commands.copy(kind), &register.text);
+        }
+
+        match kind {
+            ClipboardKind::Clipboard => self.clipboard = Some(register),
+            ClipboardKind::Primary => self.primary = Some(register),
+        }
+    }
+}
+
+fn copy(command: &[String], text: &str) -> io::Result<()> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no copy command"))?;
+
+    // Some of them stay around to serve the clipboard, so don't wait on
+    // their output.
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let res = child.stdin.take().unwrap().write_all(text.as_bytes());
+    wait(&mut child, program, Instant::now() + COMMAND_TIMEOUT)?;
+    res
+}
+
+fn paste(command: &[String]) -> io::Result<String> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no paste command"))?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let deadline = Instant::now() + COMMAND_TIMEOUT;
+
+    // Read from another thread, so that a command that never finishes
+    // writing can be given up on.
+    let mut stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = vec![];
+        let res = stdout.read_to_end(&mut output).map(|_| output);
+        let _ = sender.send(res);
+    });
+
+    let output = match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(output) => output,
+        Err(_) => Err(timed_out(program)),
+    };
+    let output = match output {
+        Ok(output) => output,
+        Err(err) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err);
+        }
+    };
+
+    if !wait(&mut child, program, deadline)?.success() {
+        return Err(io::Error::other(format!("{program} failed")));
+    }
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Wait for a command to exit, killing it if it is still running at the
+/// deadline.
+fn wait(child: &mut Child, program: &str, deadline: Instant) -> io::Result<ExitStatus> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(timed_out(program));
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn timed_out(program: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, format!("{program} timed out"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Clipboard, ClipboardCommands};
+    use crate::editor::{Register, RegisterKind};
+    use crate::term::ClipboardKind;
+
+    #[test]
+    fn clipboard() {
+        let block = Register::new("ab\ncd", RegisterKind::Blockwise);
+
+        // Without any way to reach the system clipboard, it is kept here.
+        let mut clipboard = Clipboard::new(false, None);
+        clipboard.set(ClipboardKind::Clipboard, block.clone());
+        assert_eq!(clipboard.get(ClipboardKind::Clipboard), Some(block.clone()));
+        assert_eq!(clipboard.get(ClipboardKind::Primary), None);
+
+        // What is pasted from elsewhere is charwise or linewise, but what
+        // comes back unchanged keeps its kind.
+        let commands = |text: &str| ClipboardCommands {
+            copy: vec!["true".to_string()],
+            paste: vec!["printf".to_string(), text.to_string()],
+            copy_primary: vec![],
+            paste_primary: vec![],
+        };
+
+        let mut clipboard = Clipboard::new(false, Some(commands("ab\ncd")));
+        clipboard.set(ClipboardKind::Clipboard, block.clone());
+        assert_eq!(clipboard.get(ClipboardKind::Clipboard), Some(block));
+
+        let clipboard = Clipboard::new(false, Some(commands("line\n")));
+        assert_eq!(
+            clipboard.get(ClipboardKind::Clipboard),
+            Some(Register::new("line\n", RegisterKind::Linewise))
+        );
+
+        // A paste command that doesn't answer is given up on.
+        let mut clipboard = Clipboard::new(
+            false,
+            Some(ClipboardCommands {
+                paste: vec!["sleep".to_string(), "10".to_string()],
+                ..commands("")
+            }),
+        );
+        clipboard.set(
+            ClipboardKind::Clipboard,
+            Register::new("ab", RegisterKind::Charwise),
+        );
+        let start = Instant::now();
+        assert_eq!(
+            clipboard.get(ClipboardKind::Clipboard),
+            Some(Register::new("ab", RegisterKind::Charwise))
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,341 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use bitflags::bitflags;
+
+use super::EditorState;
+use crate::ui::ControlFlow;
+
+pub type CommandResult = Result<ControlFlow, CommandError>;
+
+/// A parsed ex-style command, e.g. `:3,10d` or `:w! foo.txt`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command<'a> {
+    pub range: Option<LineRange>,
+    pub name: &'a str,
+    pub bang: bool,
+    pub args: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: Address,
+    pub end: Address,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    pub base: AddressBase,
+    pub offset: isize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressBase {
+    /// A one-based line number.
+    Line(usize),
+
+    /// `.`, the cursor line.
+    Current,
+
+    /// `$`, the last line.
+    Last,
+}
+
+impl<'a> Command<'a> {
+    pub fn parse(s: &'a str) -> Result<Self, CommandError> {
+        let mut s = s.trim_start_matches(|c: char| c == ':' || c.is_whitespace());
+
+        let range = parse_range(&mut s)?;
+        s = s.trim_start();
+
+        let name_len = match s.chars().next() {
+            Some(c) if c.is_ascii_alphabetic() => s
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(s.len()),
+            Some(c) if !c.is_whitespace() => c.len_utf8(),
+            _ => 0,
+        };
+        let (name, mut rest) = s.split_at(name_len);
+
+        let bang = match rest.strip_prefix('!') {
+            Some(after) if !name.is_empty() => {
+                rest = after;
+                true
+            }
+            _ => false,
+        };
+
+        Ok(Self {
+            range,
+            name,
+            bang,
+            args: rest.trim(),
+        })
+    }
+
+    /// Resolve the range to zero-based line indices, defaulting to the
+    /// current line.
+    pub fn line_range(
+        &self,
+        current_line: usize,
+        line_count: usize,
+    ) -> Result<RangeInclusive<usize>, CommandError> {
+        match self.range {
+            Some(range) => range.resolve(current_line, line_count),
+            None => Ok(current_line..=current_line),
+        }
+    }
+}
+
+impl LineRange {
+    pub fn resolve(
+        &self,
+        current_line: usize,
+        line_count: usize,
+    ) -> Result<RangeInclusive<usize>, CommandError> {
+        let start = self.start.resolve(current_line, line_count)?;
+        let end = self.end.resolve(current_line, line_count)?;
+
+        if start > end {
+            return Err(CommandError::new("Backwards range given"));
+        }
+
+        Ok(start..=end)
+    }
+}
+
+impl Address {
+    fn new(base: AddressBase) -> Self {
+        Self { base, offset: 0 }
+    }
+
+    pub fn resolve(&self, current_line: usize, line_count: usize) -> Result<usize, CommandError> {
+        let line = match self.base {
+            AddressBase::Line(n) => n.saturating_sub(1),
+            AddressBase::Current => current_line,
+            AddressBase::Last => line_count.saturating_sub(1),
+        };
+
+        line.checked_add_signed(self.offset)
+            .filter(|&line| line < line_count)
+            .ok_or_else(|| CommandError::new("Invalid range"))
+    }
+}
+
+fn parse_range(s: &mut &str) -> Result<Option<LineRange>, CommandError> {
+    if let Some(rest) = s.strip_prefix('%') {
+        *s = rest;
+        return Ok(Some(LineRange {
+            start: Address::new(AddressBase::Line(1)),
+            end: Address::new(AddressBase::Last),
+        }));
+    }
+
+    let Some(start) = parse_address(s)? else {
+        return Ok(None);
+    };
+
+    let end = match s.strip_prefix(',') {
+        Some(rest) => {
+            *s = rest;
+            parse_address(s)?.ok_or_else(|| CommandError::new("Invalid range"))?
+        }
+        None => start,
+    };
+
+    Ok(Some(LineRange { start, end }))
+}
+
+fn parse_address(s: &mut &str) -> Result<Option<Address>, CommandError> {
+    let base = match s.chars().next() {
+        Some('.') => {
+            *s = &s[1..];
+            Some(AddressBase::Current)
+        }
+        Some('$') => {
+            *s = &s[1..];
+            Some(AddressBase::Last)
+        }
+        Some(c) if c.is_ascii_digit() => Some(AddressBase::Line(parse_number(s)?)),
+        _ => None,
+    };
+
+    let mut offset: isize = 0;
+    let mut has_offset = false;
+
+    while let Some(sign) = s.chars().next().filter(|&c| c == '+' || c == '-') {
+        *s = &s[1..];
+        has_offset = true;
+
+        let n = match s.chars().next() {
+            Some(c) if c.is_ascii_digit() => parse_number(s)?,
+            _ => 1,
+        };
+        let n = isize::try_from(n).map_err(|_| CommandError::new("Invalid range"))?;
+
+        offset = match sign {
+            '+' => offset.checked_add(n),
+            _ => offset.checked_sub(n),
+        }
+        .ok_or_else(|| CommandError::new("Invalid range"))?;
+    }
+
+    Ok(match (base, has_offset) {
+        (Some(base), _) => Some(Address { base, offset }),
+        (None, true) => Some(Address {
+            base: AddressBase::Current,
+            offset,
+        }),
+        (None, false) => None,
+    })
+}
+
+fn parse_number(s: &mut &str) -> Result<usize, CommandError> {
+    let len = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, rest) = s.split_at(len);
+    *s = rest;
+    digits
+        .parse()
+        .map_err(|_| CommandError::new("Invalid range"))
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CommandFlags: u8 {
+        /// The command accepts a line range.
+        const RANGE = 0b01;
+        /// The command accepts a `!`.
+        const BANG  = 0b10;
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum CommandHandler {
+    /// Executed against the global state alone.
+    State(fn(&mut EditorState, &Command) -> CommandResult),
+
+    /// Passed down to the focused widget.
+    Widget,
+}
+
+#[derive(Clone)]
+pub struct CommandSpec {
+    /// The full name of the command.
+    pub name: String,
+    /// The length of the shortest accepted abbreviation.
+    pub abbrev_len: usize,
+
+    pub flags: CommandFlags,
+    pub handler: CommandHandler,
+}
+
+#[derive(Default, Clone)]
+pub struct CommandRegistry {
+    commands: Vec<CommandSpec>,
+}
+
+impl CommandRegistry {
+    /// Register a command.
+    ///
+    /// The name uses vim's notation for abbreviations, so `w[rite]`
+    /// can be invoked as `w`, `wr`, ..., or `write`.
+    pub fn register(&mut self, name: &str, flags: CommandFlags, handler: CommandHandler) {
+        let abbrev_len = name.find('[').unwrap_or(name.len());
+        let name = name.replace(['[', ']'], "");
+
+        self.commands.push(CommandSpec {
+            name,
+            abbrev_len,
+            flags,
+            handler,
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CommandSpec> {
+        if name.is_empty() {
+            return None;
+        }
+
+        // Prefer exact matches, so that e.g. `x` and `xit` can coexist.
+        self.commands
+            .iter()
+            .find(|spec| spec.name == name)
+            .or_else(|| {
+                self.commands
+                    .iter()
+                    .find(|spec| name.len() >= spec.abbrev_len && spec.name.starts_with(name))
+            })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CommandSpec> {
+        self.commands.iter()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandError(String);
+
+impl CommandError {
+    pub fn new(msg: impl Into<String>) -> Self {
+        Self(msg.into())
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        let cmd = Command::parse("w! foo.txt").unwrap();
+        assert_eq!(
+            cmd,
+            Command {
+                range: None,
+                name: "w",
+                bang: true,
+                args: "foo.txt",
+            }
+        );
+
+        let cmd = Command::parse("3,10d").unwrap();
+        assert_eq!(cmd.name, "d");
+        assert_eq!(cmd.line_range(0, 20).unwrap(), 2..=9);
+
+        let cmd = Command::parse("%s/a/b/g").unwrap();
+        assert_eq!(cmd.name, "s");
+        assert_eq!(cmd.args, "/a/b/g");
+        assert_eq!(cmd.line_range(4, 5).unwrap(), 0..=4);
+
+        let cmd = Command::parse(".,$-1").unwrap();
+        assert_eq!(cmd.name, "");
+        assert_eq!(cmd.line_range(2, 10).unwrap(), 2..=8);
+
+        let cmd = Command::parse("+2").unwrap();
+        assert_eq!(cmd.line_range(2, 10).unwrap(), 4..=4);
+
+        assert!(Command::parse("5,3d").unwrap().line_range(0, 10).is_err());
+        assert!(Command::parse("20d").unwrap().line_range(0, 10).is_err());
+    }
+
+    #[test]
+    fn registry_abbreviations() {
+        let mut registry = CommandRegistry::default();
+        registry.register("w[rite]", CommandFlags::BANG, CommandHandler::Widget);
+        registry.register("wq", CommandFlags::BANG, CommandHandler::Widget);
+
+        assert_eq!(registry.get("w").unwrap().name, "write");
+        assert_eq!(registry.get("wri").unwrap().name, "write");
+        assert_eq!(registry.get("wq").unwrap().name, "wq");
+        assert!(registry.get("writes").is_none());
+        assert!(registry.get("").is_none());
+    }
+}
@@ -0,0 +1,66 @@
+use super::{ClipboardCommands, LineNumbers};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The minimum number of lines kept above and below the cursor.
+    pub scrolloff: usize,
+
+    /// How many columns apart tab stops are.
+    pub tab_width: usize,
+
+    /// How many columns a level of indentation is.
+    pub indent_width: usize,
+
+    /// Indent with spaces rather than a tab.
+    pub expand_tab: bool,
+
+    pub line_numbers: LineNumbers,
+
+    /// Wrap lines that are too long to fit, rather than scrolling
+    /// sideways.
+    pub wrap: bool,
+
+    /// Wrap lines after whitespace where possible, rather than in the
+    /// middle of a word.
+    pub linebreak: bool,
+
+    /// Shown at the start of the rows that a wrapped line continues on.
+    pub showbreak: String,
+
+    /// How many lines the mouse wheel scrolls by.
+    pub mouse_scroll: usize,
+
+    /// Save when the terminal loses focus.
+    pub autosave: bool,
+
+    /// Reload files that changed on disk when the terminal regains focus,
+    /// if they haven't been changed in the editor.
+    pub autoread: bool,
+
+    /// Copy to the system clipboard through the terminal, with OSC 52.
+    pub osc52: bool,
+
+    /// The programs to copy and paste with, or `None` to use whichever
+    /// usual one is installed.
+    pub clipboard_commands: Option<ClipboardCommands>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            scrolloff: 5,
+            tab_width: 8,
+            indent_width: 4,
+            expand_tab: true,
+            line_numbers: LineNumbers::Off,
+            wrap: false,
+            linebreak: false,
+            showbreak: String::new(),
+            mouse_scroll: 3,
+            autosave: false,
+            autoread: true,
+            osc52: true,
+            clipboard_commands: None,
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use ropey::Rope;
+
+use super::history::History;
+use super::Signs;
+
+/// A text buffer, optionally backed by a file on disk.
+#[derive(Debug, Clone)]
+pub struct Document {
+    rope: Rope,
+    path: Option<PathBuf>,
+
+    history: History,
+    saved_revision: usize,
+
+    /// When the file was modified, as of when it was last read or written.
+    disk_modified: Option<SystemTime>,
+
+    signs: Signs,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::from_rope(Rope::new(), None)
+    }
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn from_rope(rope: Rope, path: Option<PathBuf>) -> Self {
+        let history = History::default();
+        let saved_revision = history.revision();
+
+        Self {
+            rope,
+            path,
+
+            history,
+            saved_revision,
+
+            disk_modified: None,
+
+            signs: Signs::default(),
+        }
+    }
+
+    /// Open the file at `path`.
+    ///
+    /// If the file doesn't exist, an empty document is created which will
+    /// be written to `path` when saved.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+
+        let rope = match File::open(&path) {
+            Ok(file) => Rope::from_reader(BufReader::new(file))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Rope::new(),
+            Err(err) => return Err(err),
+        };
+
+        let mut doc = Self::from_rope(rope, Some(path));
+        doc.disk_modified = doc.path().and_then(modified_time);
+        Ok(doc)
+    }
+
+    pub fn rope(&self) -> &Rope {
+        &self.rope
+    }
+
+    pub fn signs(&self) -> &Signs {
+        &self.signs
+    }
+
+    pub fn signs_mut(&mut self) -> &mut Signs {
+        &mut self.signs
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The path for display purposes.
+    pub fn display_name(&self) -> String {
+        match &self.path {
+            Some(path) => path.display().to_string(),
+            None => "[No Name]".to_owned(),
+        }
+    }
+
+    /// Whether the document has changed since it was last saved.
+    pub fn is_dirty(&self) -> bool {
+        self.history.has_pending() || self.saved_revision != self.history.revision()
+    }
+
+    /// Whether the file was modified by something else since it was last
+    /// read or written.
+    pub fn changed_on_disk(&self) -> bool {
+        self.path()
+            .and_then(modified_time)
+            .is_some_and(|modified| Some(modified) != self.disk_modified)
+    }
+
+    /// Read the file again, replacing the text as a single change so that it
+    /// can be undone.
+    ///
+    /// A transaction that is still open must be committed first, so that
+    /// the reload isn't recorded as part of it.
+    pub fn reload(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Err(io::Error::other("no file name"));
+        };
+        if self.history.in_transaction() {
+            return Err(io::Error::other("can't reload in the middle of a change"));
+        }
+
+        let text = fs::read_to_string(path)?;
+        let disk_modified = modified_time(path);
+
+        self.begin_transaction(0);
+        self.remove(0..self.rope.len_chars());
+        self.insert(0, &text);
+        self.commit_transaction(0);
+
+        self.saved_revision = self.history.revision();
+        self.disk_modified = disk_modified;
+
+        Ok(())
+    }
+
+    pub fn insert(&mut self, pos: usize, s: &str) {
+        if s.is_empty() || self.rope.try_insert(pos, s).is_err() {
+            return;
+        }
+        self.history.record_insert(pos, s);
+    }
+
+    pub fn insert_char(&mut self, pos: usize, c: char) {
+        self.insert(pos, c.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Remove a range of characters, ignoring out of bounds ranges.
+    pub fn remove(&mut self, range: Range<usize>) {
+        let Some(slice) = self
+            .rope
+            .get_slice(range.clone())
+            .filter(|_| !range.is_empty())
+        else {
+            return;
+        };
+        let text = slice.to_string();
+
+        self.rope.remove(range.clone());
+        self.history.record_remove(range.start, &text);
+    }
+
+    /// Group the following changes into one undo step, until
+    /// [`commit_transaction`](Self::commit_transaction) is called.
+    pub fn begin_transaction(&mut self, cursor: usize) {
+        self.history.begin(cursor);
+    }
+
+    pub fn commit_transaction(&mut self, cursor: usize) {
+        self.history.commit(cursor);
+    }
+
+    /// Undo the last change, returning the new cursor position.
+    pub fn undo(&mut self) -> Option<usize> {
+        self.history.undo(&mut self.rope)
+    }
+
+    /// Redo the last undone change, returning the new cursor position.
+    pub fn redo(&mut self) -> Option<usize> {
+        self.history.redo(&mut self.rope)
+    }
+
+    /// Write the document to its path.
+    pub fn save(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Err(io::Error::other("no file name"));
+        };
+
+        write_atomic(path, &self.rope)?;
+        self.saved_revision = self.history.revision();
+        self.disk_modified = modified_time(path);
+
+        Ok(())
+    }
+
+    /// Write the document to `path`, which becomes the document's path.
+    pub fn save_as(&mut self, path: impl Into<PathBuf>) -> io::Result<()> {
+        let path = path.into();
+
+        write_atomic(&path, &self.rope)?;
+        self.disk_modified = modified_time(&path);
+        self.path = Some(path);
+        self.saved_revision = self.history.revision();
+
+        Ok(())
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Write to a temporary file next to `path`, then rename it over `path`,
+/// so that the file is never left half-written.
+fn write_atomic(path: &Path, rope: &Rope) -> io::Result<()> {
+    // Write through symlinks rather than replacing them.
+    let path = match fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => path.to_owned(),
+        Err(err) => return Err(err),
+    };
+
+    let permissions = match fs::metadata(&path) {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"))?;
+
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let res = (|| {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)?;
+
+        let mut writer = BufWriter::new(file);
+        for chunk in rope.chunks() {
+            writer.write_all(chunk.as_bytes())?;
+        }
+
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.sync_all()?;
+
+        fs::rename(&tmp_path, &path)
+    })();
+
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::SystemTime;
+
+    use super::Document;
+
+    #[test]
+    fn save_and_open() {
+        let dir = std::env::temp_dir().join(format!("text_editor_doc_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+
+        let mut doc = Document::open(&path).unwrap();
+        assert!(doc.rope().len_chars() == 0 && !doc.is_dirty());
+
+        doc.insert(0, "hello\nworld");
+        assert!(doc.is_dirty());
+
+        doc.save().unwrap();
+        assert!(!doc.is_dirty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello\nworld");
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let mut doc = Document::open(&path).unwrap();
+        doc.remove(0..6);
+        doc.save().unwrap();
+
+        doc.undo();
+        assert!(doc.is_dirty());
+        doc.redo();
+        assert!(!doc.is_dirty());
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "world");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // no temporary files left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // changed by something else
+        assert!(!doc.changed_on_disk());
+        fs::write(&path, "changed").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        assert!(doc.changed_on_disk());
+
+        // not in the middle of a change, even one that changed nothing yet
+        doc.begin_transaction(0);
+        assert!(doc.reload().is_err());
+        doc.commit_transaction(0);
+
+        doc.reload().unwrap();
+        assert!(!doc.changed_on_disk() && !doc.is_dirty());
+        assert_eq!(doc.rope(), "changed");
+        doc.undo();
+        assert_eq!(doc.rope(), "world");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,13 +1,22 @@
+use std::io;
+use std::path::PathBuf;
+
+use super::command::*;
+use super::document::Document;
 use super::event::{CommandModeEvent, EditorRootEvent};
 use super::pane::Pane;
 use super::text_field::TextField;
 use super::EditorState;
-use crate::buffer::Buffer;
+use crate::buffer::{Buffer, Cell};
 use crate::event::*;
+use crate::style::{Color, Style};
 use crate::ui::*;
 
+/// How many of the keys of an incomplete command are shown.
+const PENDING_KEYS_WIDTH: usize = 10;
+
 pub struct EditorRoot {
-    main: Box<dyn Widget<EditorState>>,
+    main: Pane,
     main_buf: Buffer,
 
     cmd_line: TextField,
@@ -18,8 +27,14 @@ pub struct EditorRoot {
 
 impl Default for EditorRoot {
     fn default() -> Self {
+        Self::new(Document::new())
+    }
+}
+
+impl EditorRoot {
+    pub fn new(doc: Document) -> Self {
         Self {
-            main: Box::<Pane>::default(),
+            main: Pane::new(doc),
             main_buf: Buffer::new(0, 0),
 
             cmd_line: TextField::default(),
@@ -28,12 +43,124 @@ impl Default for EditorRoot {
             command_mode: false,
         }
     }
+
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        Ok(Self::new(Document::open(path)?))
+    }
 }
 
 impl Widget<EditorState> for EditorRoot {
     fn handle_event(&mut self, state: &mut EditorState, event: &Event) -> Option<ControlFlow> {
+        // The document needs to know about focus whatever mode we're in, and
+        // it shouldn't hide the last message.
+        if let EventKind::FocusGained | EventKind::FocusLost = event.kind {
+            return self.main.handle_event(state, event);
+        }
+
+        // Nor should releasing the key that ran a command, like the enter
+        // after `:w`.
+        let cleared = !matches!(
+            event.kind,
+            EventKind::Key(KeyEvent {
+                kind: KeyEventKind::Release,
+                ..
+            })
+        ) && state.clear_message();
+
+        let handled = self.dispatch_event(state, event);
+
+        // Even if nothing handled the event, the message has to go.
+        if cleared {
+            handled.or(Some(ControlFlow::Continue))
+        } else {
+            handled
+        }
+    }
+
+    fn update(&mut self, state: &mut EditorState) -> Update {
+        if self.command_mode {
+            self.cmd_line.update(state)
+        } else {
+            self.main.update(state)
+        }
+    }
+
+    fn render(&mut self, state: &EditorState, buf: &mut Buffer) {
+        if buf.height() < 2 {
+            return;
+        }
+
+        self.main_buf
+            .resize_and_clear(buf.width(), buf.height() - 1);
+        self.main.render(state, &mut self.main_buf);
+        buf.blit(0, 0, &self.main_buf, !self.command_mode);
+
+        let cmd_line_y = buf.height() - 1;
+
+        if self.command_mode {
+            self.cmd_line_buf
+                .resize_and_clear(buf.width().saturating_sub(1), 1);
+            self.cmd_line.render(state, &mut self.cmd_line_buf);
+
+            buf.set_grapheme(0, cmd_line_y, ":");
+            buf.blit(1, cmd_line_y, &self.cmd_line_buf, true);
+        } else if let Some(message) = state.message() {
+            // Messages of several lines, like `:registers`, cover the bottom
+            // of the document.
+            let lines: Vec<&str> = message.text.lines().collect();
+            let top = buf.height().saturating_sub(lines.len().max(1));
+
+            let style = Style {
+                fg: if message.is_error {
+                    Color::Red
+                } else {
+                    Color::Default
+                },
+                ..Default::default()
+            };
+
+            for (y, line) in (top..).zip(lines) {
+                if y != cmd_line_y {
+                    for x in 0..buf.width() {
+                        buf[[x, y]] = Cell::default();
+                    }
+                }
+
+                let end = buf.set_str(0, y, line);
+                for x in 0..end {
+                    buf[[x, y]].style = style;
+                }
+            }
+        }
+
+        // The keys of an incomplete command, towards the right like vim's
+        // `showcmd`.
+        if !self.command_mode {
+            let pending = self.main.pending_keys();
+            let skip = pending.chars().count().saturating_sub(PENDING_KEYS_WIDTH);
+            let pending: String = pending.chars().skip(skip).collect();
+            let x = buf.width().saturating_sub(PENDING_KEYS_WIDTH + 1);
+            buf.set_str(x, cmd_line_y, &pending);
+        }
+    }
+}
+
+impl EditorRoot {
+    fn dispatch_event(&mut self, state: &mut EditorState, event: &Event) -> Option<ControlFlow> {
+        if let EventKind::Mouse { kind, y, .. } = event.kind {
+            return self.handle_mouse_event(state, event, kind, y);
+        }
+
         if self.command_mode {
             match state.key_maps.command_mode(event) {
+                Some(CommandModeEvent::Execute) => {
+                    let cmd = self.cmd_line.value();
+                    self.cmd_line.clear();
+                    self.command_mode = false;
+                    let control_flow = self.execute_command(state, &cmd);
+                    state.registers.set_last_command(cmd);
+                    Some(control_flow)
+                }
                 Some(CommandModeEvent::Escape) => {
                     self.cmd_line.clear();
                     self.command_mode = false;
@@ -51,7 +178,8 @@ impl Widget<EditorState> for EditorRoot {
                             self.command_mode = true;
                             Some(ControlFlow::Continue)
                         }
-                        EditorRootEvent::Quit => Some(ControlFlow::Exit),
+                        EditorRootEvent::Suspend => Some(ControlFlow::Suspend),
+                        EditorRootEvent::Quit => Some(self.execute_command(state, "quit")),
                     },
                     None => self.main.handle_event(state, event),
                 }
@@ -59,33 +187,87 @@ impl Widget<EditorState> for EditorRoot {
         }
     }
 
-    fn update(&mut self, state: &mut EditorState) -> ControlFlow {
-        if self.command_mode {
-            self.cmd_line.update(state)
+    /// Send a mouse event to the widget under it, except for drags and
+    /// releases, which go to the widget that was clicked on.
+    fn handle_mouse_event(
+        &mut self,
+        state: &mut EditorState,
+        event: &Event,
+        kind: MouseKind,
+        y: usize,
+    ) -> Option<ControlFlow> {
+        let main_height = self.main_buf.height();
+
+        let to_main = match kind {
+            MouseKind::Drag | MouseKind::Release => !self.command_mode,
+            _ => y < main_height,
+        };
+
+        if to_main {
+            // Clicking on the document leaves the command line.
+            if self.command_mode && kind == MouseKind::Press {
+                self.cmd_line.clear();
+                self.command_mode = false;
+            }
+
+            self.main.handle_event(state, event)
+        } else if self.command_mode {
+            self.cmd_line
+                .handle_event(state, &event.relative_to(1, main_height))
         } else {
-            self.main.update(state)
+            None
         }
     }
 
-    fn render(&mut self, buf: &mut Buffer) {
-        if buf.height() < 2 {
-            return;
+    fn execute_command(&mut self, state: &mut EditorState, cmd: &str) -> ControlFlow {
+        match self.try_execute_command(state, cmd) {
+            Ok(control_flow) => control_flow,
+            Err(err) => {
+                state.show_error(err.to_string());
+                ControlFlow::Continue
+            }
         }
+    }
 
-        self.main_buf
-            .resize_and_clear(buf.width(), buf.height() - 1);
-        self.main.render(&mut self.main_buf);
-        buf.blit(0, 0, &self.main_buf, !self.command_mode);
+    fn try_execute_command(&mut self, state: &mut EditorState, cmd: &str) -> CommandResult {
+        let cmd = Command::parse(cmd)?;
 
-        if self.command_mode {
-            let cmd_line_y = buf.height() - 1;
+        // A range on its own jumps to a line.
+        if cmd.name.is_empty() {
+            return match cmd.range {
+                Some(_) => self
+                    .main
+                    .execute_command(state, &cmd)
+                    .unwrap_or(Ok(ControlFlow::Continue)),
+                None => Ok(ControlFlow::Continue),
+            };
+        }
 
-            self.cmd_line_buf
-                .resize_and_clear(buf.width().saturating_sub(1), 1);
-            self.cmd_line.render(&mut self.cmd_line_buf);
+        let spec = state
+            .commands
+            .get(cmd.name)
+            .cloned()
+            .ok_or_else(|| CommandError::new(format!("Not an editor command: {}", cmd.name)))?;
 
-            buf[[0, cmd_line_y]].c = ':';
-            buf.blit(1, cmd_line_y, &self.cmd_line_buf, true);
+        if cmd.range.is_some() && !spec.flags.contains(CommandFlags::RANGE) {
+            return Err(CommandError::new("No range allowed"));
+        }
+
+        if cmd.bang && !spec.flags.contains(CommandFlags::BANG) {
+            return Err(CommandError::new("No ! allowed"));
+        }
+
+        let cmd = Command {
+            name: &spec.name,
+            ..cmd
+        };
+
+        match spec.handler {
+            CommandHandler::State(f) => f(state, &cmd),
+            CommandHandler::Widget => self
+                .main
+                .execute_command(state, &cmd)
+                .unwrap_or_else(|| Err(CommandError::new(format!("Can't use {} here", spec.name)))),
         }
     }
 }
@@ -1,20 +1,77 @@
-#[derive(Debug, Clone)]
+use super::grammar::Operator;
+use super::motion::{Motion, TextObject};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum NormalModeEvent {
     InsertMode,
+    Visual,
+    VisualLine,
+    VisualBlock,
 
-    MoveUp,
-    MoveDown,
-    MoveLeft,
-    MoveRight,
+    Undo,
+    Redo,
 
-    MoveHome,
-    MoveEnd,
+    Motion(Motion),
+
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+
+    ScrollCenter,
+    ScrollTop,
+    ScrollBottom,
+
+    /// Read a register name, for the next yank, delete or put.
+    SelectRegister,
+    /// Wait for a motion or text object to apply an operator to.
+    Operator(Operator),
+    YankLine,
+    /// Put after or before the cursor.
+    Put,
+    PutBefore,
+}
+
+#[derive(Debug, Clone)]
+pub enum VisualModeEvent {
+    /// Switch to another visual mode, or back to normal mode from the same
+    /// one.
+    Visual,
+    VisualLine,
+    VisualBlock,
+    Escape,
+
+    Motion(Motion),
+
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+
+    /// Move the cursor to the other end of the selection.
+    SwapEnds,
+
+    SelectRegister,
+    Operator(Operator),
+    /// Replace the selection, with `PutKeep` leaving the registers as they
+    /// were rather than storing what was replaced.
+    Put,
+    PutKeep,
+}
+
+/// The keys after an operator, before it has what it applies to.
+#[derive(Debug, Clone)]
+pub enum OperatorPendingEvent {
+    Motion(Motion),
+    TextObject(TextObject),
+    /// The same operator again applies it to whole lines.
+    Operator(Operator),
+    Escape,
 }
 
 #[derive(Debug, Clone)]
-pub enum InsertModeEvent<'a> {
+pub enum InsertModeEvent {
     InsertChar(char),
-    InsertString(&'a str),
 
     Delete,
     Backspace,
@@ -27,17 +84,22 @@ pub enum InsertModeEvent<'a> {
     MoveHome,
     MoveEnd,
 
+    PageUp,
+    PageDown,
+
     Escape,
 }
 
 #[derive(Debug, Clone)]
 pub enum CommandModeEvent {
+    Execute,
     Escape,
 }
 
 #[derive(Debug, Clone)]
 pub enum EditorRootEvent {
     CommandMode,
+    Suspend,
     Quit,
 }
 
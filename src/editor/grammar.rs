@@ -0,0 +1,327 @@
+//! Normal mode commands of the form `[count]["x]operator[count]motion`,
+//! read a key at a time on top of the key maps.
+
+use super::event::{NormalModeEvent, OperatorPendingEvent};
+use super::keymaps::{KeyMaps, KeyMatch};
+use super::motion::{Motion, TextObject};
+use super::register::Registers;
+use crate::event::*;
+
+/// The largest count a command takes, so that a mistyped one can't make it
+/// run for ever or use up the memory.
+pub const MAX_COUNT: usize = 9999;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Dedent,
+    Lowercase,
+    Uppercase,
+    /// Indent lines by the brackets opened on the lines above them.
+    Reindent,
+}
+
+/// What an operator applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Motion(Motion),
+    TextObject(TextObject),
+    /// Whole lines, from typing the operator twice like `dd`.
+    Line,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Event(NormalModeEvent),
+    Operator(Operator, Target),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalCommand {
+    pub action: Action,
+    /// The counts before and after the register and the operator
+    /// multiplied together, up to [`MAX_COUNT`], or `None` if none was
+    /// typed.
+    pub count: Option<usize>,
+    pub register: Option<char>,
+}
+
+#[derive(Debug, Default)]
+pub struct Grammar {
+    /// A count typed before the register, like the 2 in `2"a3dw`.
+    register_count: Option<usize>,
+    count: Option<usize>,
+    register: Option<char>,
+    awaiting_register: bool,
+    operator: Option<Operator>,
+    motion_count: Option<usize>,
+
+    pending_keys: Vec<KeyEvent>,
+    /// The keys of the command so far, to show while it is incomplete.
+    typed: String,
+}
+
+impl Grammar {
+    /// Read a key, returning the command once it is complete.
+    pub fn handle(&mut self, key_maps: &KeyMaps, event: &Event) -> KeyMatch<NormalCommand> {
+        let EventKind::Key(key_event) = event.kind else {
+            return self.abandon();
+        };
+
+        // Releases don't interrupt a command.
+        if key_event.kind == KeyEventKind::Release {
+            return KeyMatch::Unmatched;
+        }
+
+        if self.awaiting_register {
+            self.awaiting_register = false;
+            return match key_event.key_code {
+                KeyCode::Char(name) if Registers::is_valid(name) => {
+                    self.register = Some(name);
+                    self.register_count = self.count.take();
+                    self.typed.push(name);
+                    KeyMatch::Consumed
+                }
+                _ => self.abandon(),
+            };
+        }
+
+        if let Some(digit) = self.count_digit(&key_event) {
+            let count = match self.operator {
+                None => &mut self.count,
+                Some(_) => &mut self.motion_count,
+            };
+            *count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+            self.typed.push_str(&key_name(&key_event));
+            return KeyMatch::Consumed;
+        }
+
+        let Some(operator) = self.operator else {
+            return match key_maps.normal_mode(&mut self.pending_keys, event) {
+                KeyMatch::Matched(NormalModeEvent::SelectRegister) => {
+                    self.awaiting_register = true;
+                    self.typed.push_str(&key_name(&key_event));
+                    KeyMatch::Consumed
+                }
+                KeyMatch::Matched(NormalModeEvent::Operator(operator)) => {
+                    self.operator = Some(operator);
+                    self.typed.push_str(&key_name(&key_event));
+                    KeyMatch::Consumed
+                }
+                KeyMatch::Matched(event) => self.finish(Action::Event(event)),
+                KeyMatch::Consumed => self.continue_sequence(&key_event),
+                // Let something else have a key that isn't a command, like
+                // `:`.
+                KeyMatch::Unmatched => {
+                    self.reset();
+                    KeyMatch::Unmatched
+                }
+            };
+        };
+
+        match key_maps.operator_pending(&mut self.pending_keys, event) {
+            KeyMatch::Matched(OperatorPendingEvent::Motion(motion)) => {
+                self.finish(Action::Operator(operator, Target::Motion(motion)))
+            }
+            KeyMatch::Matched(OperatorPendingEvent::TextObject(object)) => {
+                self.finish(Action::Operator(operator, Target::TextObject(object)))
+            }
+            KeyMatch::Matched(OperatorPendingEvent::Operator(other)) if other == operator => {
+                self.finish(Action::Operator(operator, Target::Line))
+            }
+            KeyMatch::Consumed => self.continue_sequence(&key_event),
+            // escape, or a different operator
+            _ => self.abandon(),
+        }
+    }
+
+    /// The keys typed so far of an incomplete command.
+    pub fn pending(&self) -> &str {
+        &self.typed
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// A digit of a count. Zero only continues a count, as on its own it
+    /// moves to the start of the line.
+    fn count_digit(&self, key_event: &KeyEvent) -> Option<usize> {
+        let KeyCode::Char(c) = key_event.key_code else {
+            return None;
+        };
+        if !self.pending_keys.is_empty() || !(key_event.modifiers - Modifiers::SHIFT).is_empty() {
+            return None;
+        }
+
+        let started = match self.operator {
+            None => self.count.is_some(),
+            Some(_) => self.motion_count.is_some(),
+        };
+        match c {
+            '1'..='9' => c.to_digit(10).map(|digit| digit as usize),
+            '0' if started => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Keep a key that is part of a sequence, unless the sequence turned
+    /// out to be invalid.
+    fn continue_sequence(&mut self, key_event: &KeyEvent) -> KeyMatch<NormalCommand> {
+        if self.pending_keys.is_empty() {
+            return self.abandon();
+        }
+
+        self.typed.push_str(&key_name(key_event));
+        KeyMatch::Consumed
+    }
+
+    /// Give up on the command so far, if there is one.
+    fn abandon(&mut self) -> KeyMatch<NormalCommand> {
+        let was_pending = !self.typed.is_empty();
+        self.reset();
+        match was_pending {
+            true => KeyMatch::Consumed,
+            false => KeyMatch::Unmatched,
+        }
+    }
+
+    fn finish(&mut self, action: Action) -> KeyMatch<NormalCommand> {
+        let count = [self.register_count, self.count, self.motion_count]
+            .into_iter()
+            .flatten()
+            .reduce(usize::saturating_mul)
+            .map(|count| count.min(MAX_COUNT));
+
+        let command = NormalCommand {
+            action,
+            count,
+            register: self.register,
+        };
+        self.reset();
+        KeyMatch::Matched(command)
+    }
+}
+
+/// How a key is shown among the pending keys, like vim's `showcmd`.
+pub fn key_name(key_event: &KeyEvent) -> String {
+    match key_event.key_code {
+        KeyCode::Char(c) if key_event.modifiers.contains(Modifiers::CTRL) => {
+            format!("^{}", c.to_ascii_uppercase())
+        }
+        KeyCode::Char(c) => c.to_string(),
+        key_code => format!("<{key_code:?}>"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Grammar, NormalCommand, Operator, Target, MAX_COUNT};
+    use crate::editor::event::NormalModeEvent;
+    use crate::editor::keymaps::{KeyMaps, KeyMatch};
+    use crate::editor::motion::{Motion, ObjectKind, TextObject};
+    use crate::event::*;
+    use crate::input::Bytes;
+
+    /// Type keys, returning the command they complete, if any.
+    fn parse(grammar: &mut Grammar, keys: &str) -> Option<NormalCommand> {
+        let key_maps = KeyMaps::default();
+        let mut command = None;
+        for c in keys.chars() {
+            let event = Event {
+                bytes: Bytes::default(),
+                kind: EventKind::Key(KeyEvent::key(KeyCode::Char(c))),
+            };
+            if let KeyMatch::Matched(matched) = grammar.handle(&key_maps, &event) {
+                command = Some(matched);
+            }
+        }
+        command
+    }
+
+    #[test]
+    fn grammar() {
+        let mut grammar = Grammar::default();
+
+        let command = |action, count, register| {
+            Some(NormalCommand {
+                action,
+                count,
+                register,
+            })
+        };
+
+        assert_eq!(
+            parse(&mut grammar, "3dw"),
+            command(
+                Action::Operator(Operator::Delete, Target::Motion(Motion::WordForward)),
+                Some(3),
+                None
+            )
+        );
+        assert_eq!(
+            parse(&mut grammar, "2\"a3yy"),
+            command(
+                Action::Operator(Operator::Yank, Target::Line),
+                Some(6),
+                Some('a')
+            )
+        );
+        assert_eq!(
+            parse(&mut grammar, "c$"),
+            command(
+                Action::Operator(Operator::Change, Target::Motion(Motion::LineEnd)),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            parse(&mut grammar, ">ip"),
+            command(
+                Action::Operator(
+                    Operator::Indent,
+                    Target::TextObject(TextObject::inner(ObjectKind::Paragraph))
+                ),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            parse(&mut grammar, "gUU"),
+            command(
+                Action::Operator(Operator::Uppercase, Target::Line),
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            parse(&mut grammar, "10j"),
+            command(
+                Action::Event(NormalModeEvent::Motion(Motion::Down)),
+                Some(10),
+                None
+            )
+        );
+        assert_eq!(
+            parse(&mut grammar, "99999999999999999999p"),
+            command(Action::Event(NormalModeEvent::Put), Some(MAX_COUNT), None)
+        );
+        assert_eq!(
+            parse(&mut grammar, "0"),
+            command(
+                Action::Event(NormalModeEvent::Motion(Motion::LineStart)),
+                None,
+                None
+            )
+        );
+
+        // An operator that doesn't match is dropped along with the rest.
+        assert_eq!(parse(&mut grammar, "2dy"), None);
+        assert_eq!(grammar.pending(), "");
+        assert_eq!(parse(&mut grammar, "2gu"), None);
+        assert_eq!(grammar.pending(), "2gu");
+    }
+}
@@ -0,0 +1,257 @@
+//! The gutter to the left of the text, with a sign column and line numbers.
+
+use std::collections::BTreeMap;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::buffer::Buffer;
+use crate::grapheme;
+use crate::style::{Attributes, Color, Style, Underline, Weight};
+
+/// How many columns a sign takes up.
+const SIGN_WIDTH: usize = 2;
+
+const LINE_NUMBER_STYLE: Style = Style {
+    fg: Color::Yellow,
+    bg: Color::Default,
+    weight: Weight::Normal,
+    underline: Underline::None,
+    underline_color: Color::Default,
+    attributes: Attributes::empty(),
+};
+
+const CURSOR_LINE_NUMBER_STYLE: Style = Style {
+    weight: Weight::Bold,
+    ..LINE_NUMBER_STYLE
+};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineNumbers {
+    #[default]
+    Off,
+    Absolute,
+    /// How far each line is from the cursor.
+    Relative,
+    /// Relative, except for the cursor's line.
+    Hybrid,
+}
+
+/// A mark shown next to a line, such as a diagnostic, a changed line or a
+/// breakpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sign {
+    text: String,
+    pub style: Style,
+    /// When a line has more than one sign, the highest priority one is
+    /// shown.
+    pub priority: i32,
+}
+
+impl Sign {
+    /// Anything in `text` after the first two columns is dropped.
+    pub fn new(text: &str, style: Style, priority: i32) -> Self {
+        let mut width = 0;
+        let text = text
+            .graphemes(true)
+            .take_while(|grapheme| {
+                width += grapheme::width(grapheme);
+                width <= SIGN_WIDTH
+            })
+            .collect();
+
+        Self {
+            text,
+            style,
+            priority,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// The signs in a document, by line.
+///
+/// Signs are kept in named groups, so that each thing that adds them can
+/// replace its own without touching the others. They aren't moved by edits,
+/// so it's up to each group to keep them up to date.
+#[derive(Default, Debug, Clone)]
+pub struct Signs {
+    groups: BTreeMap<String, BTreeMap<usize, Sign>>,
+}
+
+impl Signs {
+    pub fn set(&mut self, group: &str, line: usize, sign: Sign) {
+        self.groups
+            .entry(group.to_owned())
+            .or_default()
+            .insert(line, sign);
+    }
+
+    pub fn remove(&mut self, group: &str, line: usize) {
+        if let Some(signs) = self.groups.get_mut(group) {
+            signs.remove(&line);
+            if signs.is_empty() {
+                self.groups.remove(group);
+            }
+        }
+    }
+
+    /// Remove all of a group's signs.
+    pub fn clear(&mut self, group: &str) {
+        self.groups.remove(group);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// The sign to show for a line.
+    pub fn get(&self, line: usize) -> Option<&Sign> {
+        self.groups
+            .values()
+            .filter_map(|signs| signs.get(&line))
+            .max_by_key(|sign| sign.priority)
+    }
+}
+
+/// The gutter as it is for one render, sized to fit the document.
+pub struct Gutter<'a> {
+    line_numbers: LineNumbers,
+    signs: &'a Signs,
+
+    len_lines: usize,
+    cursor_line: usize,
+}
+
+impl<'a> Gutter<'a> {
+    pub fn new(
+        line_numbers: LineNumbers,
+        signs: &'a Signs,
+        len_lines: usize,
+        cursor_line: usize,
+    ) -> Self {
+        Self {
+            line_numbers,
+            signs,
+
+            len_lines,
+            cursor_line,
+        }
+    }
+
+    /// The sign column is only shown while there are signs.
+    fn sign_width(&self) -> usize {
+        match self.signs.is_empty() {
+            true => 0,
+            false => SIGN_WIDTH,
+        }
+    }
+
+    fn number_width(&self) -> usize {
+        match self.line_numbers {
+            LineNumbers::Off => 0,
+            _ => self.len_lines.max(1).ilog10() as usize + 1,
+        }
+    }
+
+    /// How many columns the gutter takes up, including a space between the
+    /// line numbers and the text.
+    pub fn width(&self) -> usize {
+        match self.number_width() {
+            0 => self.sign_width(),
+            number_width => self.sign_width() + number_width + 1,
+        }
+    }
+
+    /// Draw the gutter for a row of the screen. Only the first row of a
+    /// wrapped line gets a sign and a number.
+    pub fn render(&self, buf: &mut Buffer, y: usize, line_y: usize, first_row: bool) {
+        if !first_row {
+            return;
+        }
+
+        let sign_width = self.sign_width();
+        if let Some(sign) = self.signs.get(line_y).filter(|_| sign_width > 0) {
+            let end = buf.set_str(0, y, sign.text());
+            for x in 0..end {
+                buf[[x, y]].style = sign.style;
+            }
+        }
+
+        let number_width = self.number_width();
+        let is_cursor_line = line_y == self.cursor_line;
+        let distance = line_y.abs_diff(self.cursor_line);
+
+        let number = match self.line_numbers {
+            LineNumbers::Off => return,
+            LineNumbers::Absolute => format!("{:>number_width$}", line_y + 1),
+            LineNumbers::Relative => format!("{distance:>number_width$}"),
+            // the cursor's line stands out by being on the left
+            LineNumbers::Hybrid if is_cursor_line => format!("{:<number_width$}", line_y + 1),
+            LineNumbers::Hybrid => format!("{distance:>number_width$}"),
+        };
+
+        let end = buf.set_str(sign_width, y, &number);
+        for x in sign_width..end {
+            buf[[x, y]].style = match is_cursor_line {
+                true => CURSOR_LINE_NUMBER_STYLE,
+                false => LINE_NUMBER_STYLE,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_numbers_and_signs() {
+        let render = |line_numbers, signs: &Signs, len_lines| {
+            let gutter = Gutter::new(line_numbers, signs, len_lines, 9);
+            let mut buf = Buffer::new(gutter.width(), 3);
+            for (y, line_y) in [8, 9, 10].into_iter().enumerate() {
+                gutter.render(&mut buf, y, line_y, true);
+            }
+
+            (0..3)
+                .map(|y| {
+                    (0..buf.width())
+                        .map(|x| buf[[x, y]].symbol.as_str())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut signs = Signs::default();
+        assert_eq!(render(LineNumbers::Off, &signs, 100), ["", "", ""]);
+        assert_eq!(
+            render(LineNumbers::Absolute, &signs, 100),
+            ["  9 ", " 10 ", " 11 "]
+        );
+        assert_eq!(
+            render(LineNumbers::Relative, &signs, 100),
+            ["  1 ", "  0 ", "  1 "]
+        );
+        assert_eq!(
+            render(LineNumbers::Hybrid, &signs, 11),
+            [" 1 ", "10 ", " 1 "]
+        );
+
+        signs.set("diff", 8, Sign::new("+", Style::default(), 0));
+        signs.set("diff", 10, Sign::new("~", Style::default(), 0));
+        signs.set("lint", 10, Sign::new("E>>", Style::default(), 10));
+        assert_eq!(
+            render(LineNumbers::Absolute, &signs, 11),
+            ["+  9 ", "  10 ", "E>11 "]
+        );
+
+        signs.clear("lint");
+        signs.remove("diff", 8);
+        assert_eq!(render(LineNumbers::Off, &signs, 11), ["  ", "  ", "~ "]);
+        signs.remove("diff", 10);
+        assert!(signs.is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+use ropey::Rope;
+
+#[derive(Debug, Clone)]
+enum Change {
+    Insert { pos: usize, text: String },
+    Remove { pos: usize, text: String },
+}
+
+impl Change {
+    fn apply(&self, rope: &mut Rope) {
+        match self {
+            Self::Insert { pos, text } => rope.insert(*pos, text),
+            Self::Remove { pos, text } => rope.remove(*pos..(*pos + text.chars().count())),
+        }
+    }
+
+    fn invert(&self) -> Self {
+        match self.clone() {
+            Self::Insert { pos, text } => Self::Remove { pos, text },
+            Self::Remove { pos, text } => Self::Insert { pos, text },
+        }
+    }
+
+    /// Where the cursor should be after the change is made.
+    fn cursor_after(&self) -> usize {
+        match self {
+            Self::Insert { pos, text } => pos + text.chars().count(),
+            Self::Remove { pos, .. } => *pos,
+        }
+    }
+}
+
+/// A group of changes that are undone and redone together.
+#[derive(Debug, Clone)]
+struct Transaction {
+    changes: Vec<Change>,
+
+    cursor_before: usize,
+    cursor_after: usize,
+}
+
+impl Transaction {
+    fn new(cursor: usize) -> Self {
+        Self {
+            changes: vec![],
+            cursor_before: cursor,
+            cursor_after: cursor,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    parent: usize,
+    /// The child that redo moves to: the most recently created or undone.
+    redo_child: Option<usize>,
+
+    transaction: Transaction,
+}
+
+/// An undo tree.
+///
+/// Undoing and then making a new change starts a new branch rather than
+/// discarding the undone changes.
+#[derive(Debug, Clone)]
+pub struct History {
+    /// The first node is the root, which has no changes.
+    nodes: Vec<Node>,
+    current: usize,
+
+    pending: Option<Transaction>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node {
+                parent: 0,
+                redo_child: None,
+                transaction: Transaction::new(0),
+            }],
+            current: 0,
+
+            pending: None,
+        }
+    }
+}
+
+impl History {
+    /// Start grouping changes into a single transaction.
+    pub fn begin(&mut self, cursor: usize) {
+        if self.pending.is_none() {
+            self.pending = Some(Transaction::new(cursor));
+        }
+    }
+
+    /// Finish the current transaction, if any.
+    pub fn commit(&mut self, cursor: usize) {
+        if let Some(mut transaction) = self.pending.take() {
+            transaction.cursor_after = cursor;
+            self.push(transaction);
+        }
+    }
+
+    pub fn record_insert(&mut self, pos: usize, text: &str) {
+        self.record(Change::Insert {
+            pos,
+            text: text.to_owned(),
+        });
+    }
+
+    pub fn record_remove(&mut self, pos: usize, text: &str) {
+        self.record(Change::Remove {
+            pos,
+            text: text.to_owned(),
+        });
+    }
+
+    fn record(&mut self, change: Change) {
+        match &mut self.pending {
+            Some(transaction) => transaction.changes.push(change),
+            None => {
+                let mut transaction = Transaction::new(match &change {
+                    Change::Insert { pos, .. } | Change::Remove { pos, .. } => *pos,
+                });
+                transaction.cursor_after = change.cursor_after();
+                transaction.changes.push(change);
+
+                self.push(transaction);
+            }
+        }
+    }
+
+    fn push(&mut self, transaction: Transaction) {
+        if transaction.changes.is_empty() {
+            return;
+        }
+
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            parent: self.current,
+            redo_child: None,
+            transaction,
+        });
+
+        self.nodes[self.current].redo_child = Some(id);
+        self.current = id;
+    }
+
+    fn commit_pending(&mut self) {
+        if let Some(cursor) = self
+            .pending
+            .as_ref()
+            .and_then(|transaction| transaction.changes.last())
+            .map(Change::cursor_after)
+        {
+            self.commit(cursor);
+        } else {
+            self.pending = None;
+        }
+    }
+
+    /// Undo the current transaction, returning the cursor position
+    /// from before it was made.
+    pub fn undo(&mut self, rope: &mut Rope) -> Option<usize> {
+        self.commit_pending();
+
+        if self.current == 0 {
+            return None;
+        }
+
+        let node = &self.nodes[self.current];
+        for change in node.transaction.changes.iter().rev() {
+            change.invert().apply(rope);
+        }
+
+        let cursor = node.transaction.cursor_before;
+        let parent = node.parent;
+
+        self.nodes[parent].redo_child = Some(self.current);
+        self.current = parent;
+
+        Some(cursor)
+    }
+
+    /// Redo the most recently undone transaction, returning the cursor
+    /// position from after it was made.
+    pub fn redo(&mut self, rope: &mut Rope) -> Option<usize> {
+        self.commit_pending();
+
+        let child = self.nodes[self.current].redo_child?;
+
+        let node = &self.nodes[child];
+        for change in &node.transaction.changes {
+            change.apply(rope);
+        }
+
+        self.current = child;
+
+        Some(node.transaction.cursor_after)
+    }
+
+    /// Identifies the current state of the text.
+    pub fn revision(&self) -> usize {
+        self.current
+    }
+
+    /// Whether a transaction has begun and not yet been committed, even if
+    /// nothing has changed in it.
+    pub fn in_transaction(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Whether there are uncommitted changes.
+    pub fn has_pending(&self) -> bool {
+        self.pending
+            .as_ref()
+            .is_some_and(|transaction| !transaction.changes.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::History;
+
+    #[test]
+    fn undo_tree() {
+        let mut rope = Rope::new();
+        let mut history = History::default();
+
+        // one transaction made of several changes
+        history.begin(0);
+        rope.insert(0, "hello");
+        history.record_insert(0, "hello");
+        rope.insert(5, " world");
+        history.record_insert(5, " world");
+        history.commit(11);
+
+        rope.remove(0..6);
+        history.record_remove(0, "hello ");
+        assert_eq!(rope, "world");
+
+        assert_eq!(history.undo(&mut rope), Some(0));
+        assert_eq!(rope, "hello world");
+        assert_eq!(history.undo(&mut rope), Some(0));
+        assert_eq!(rope, "");
+        assert_eq!(history.undo(&mut rope), None);
+
+        assert_eq!(history.redo(&mut rope), Some(11));
+        assert_eq!(rope, "hello world");
+
+        // branch off, then undo back and redo into the new branch
+        rope.insert(11, "!");
+        history.record_insert(11, "!");
+
+        assert_eq!(history.undo(&mut rope), Some(11));
+        assert_eq!(rope, "hello world");
+        assert_eq!(history.redo(&mut rope), Some(12));
+        assert_eq!(rope, "hello world!");
+        assert_eq!(history.redo(&mut rope), None);
+    }
+}
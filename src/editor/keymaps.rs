@@ -1,20 +1,109 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use super::event::{
-    CommandModeEvent, EditorRootEvent, InsertModeEvent, NormalModeEvent, VSplitEvent,
+    CommandModeEvent, EditorRootEvent, InsertModeEvent, NormalModeEvent, OperatorPendingEvent,
+    VSplitEvent, VisualModeEvent,
 };
+use super::grammar::Operator;
+use super::motion::{Motion, ObjectKind, TextObject};
 use crate::event::*;
 
+/// The result of looking up a key that may be part of a sequence.
+#[derive(Debug, Clone)]
+pub enum KeyMatch<E> {
+    /// The keys so far make up a mapping.
+    Matched(E),
+
+    /// The key was used as part of a sequence, either because the sequence
+    /// is incomplete or because it turned out to be invalid.
+    Consumed,
+
+    /// The key isn't mapped.
+    Unmatched,
+}
+
+/// The key as it is looked up in a key map, or `None` if it was released.
+///
+/// Repeats count as presses, and shift is ignored for characters as it is
+/// already reflected in the character.
+fn normalize(event: &Event) -> Option<KeyEvent> {
+    let EventKind::Key(mut key_event) = event.kind else {
+        return None;
+    };
+
+    if key_event.kind == KeyEventKind::Release {
+        return None;
+    }
+    key_event.kind = KeyEventKind::Press;
+
+    if let KeyCode::Char(_) = key_event.key_code {
+        key_event.modifiers.remove(Modifiers::SHIFT);
+    }
+
+    Some(key_event)
+}
+
 #[derive(Debug)]
 struct KeyMap<E> {
-    map: HashMap<KeyEvent, E>,
+    map: HashMap<Vec<KeyEvent>, E>,
+    /// Every proper prefix of the mapped sequences.
+    prefixes: HashSet<Vec<KeyEvent>>,
 }
 
 impl<E: Clone> KeyMap<E> {
+    fn new(map: HashMap<Vec<KeyEvent>, E>) -> Self {
+        let prefixes = map
+            .keys()
+            .flat_map(|keys| (1..keys.len()).map(|len| keys[..len].to_vec()))
+            .collect();
+
+        Self { map, prefixes }
+    }
+
+    /// Add the mappings of another key map, as events of this one, where
+    /// they don't clash with this one's.
+    fn with<T: Clone>(mut self, other: &KeyMap<T>, f: impl Fn(T) -> E) -> Self {
+        for (keys, e) in &other.map {
+            self.map.entry(keys.clone()).or_insert_with(|| f(e.clone()));
+        }
+        Self::new(self.map)
+    }
+
     fn get(&self, event: &Event) -> Option<E> {
-        match &event.kind {
-            EventKind::Key(key_event) => self.map.get(key_event).cloned(),
-            _ => None,
+        let key_event = normalize(event)?;
+        self.map.get(std::slice::from_ref(&key_event)).cloned()
+    }
+
+    /// Look up a key following the `pending` keys, which are updated to
+    /// hold the keys of an incomplete sequence.
+    fn get_seq(&self, pending: &mut Vec<KeyEvent>, event: &Event) -> KeyMatch<E> {
+        // Releases don't interrupt a sequence.
+        if let EventKind::Key(KeyEvent {
+            kind: KeyEventKind::Release,
+            ..
+        }) = event.kind
+        {
+            return KeyMatch::Unmatched;
+        }
+
+        let Some(key_event) = normalize(event) else {
+            return match std::mem::take(pending).is_empty() {
+                true => KeyMatch::Unmatched,
+                false => KeyMatch::Consumed,
+            };
+        };
+
+        pending.push(key_event);
+
+        if self.prefixes.contains(pending) {
+            return KeyMatch::Consumed;
+        }
+
+        let was_sequence = pending.len() > 1;
+        match self.map.get(&std::mem::take(pending)) {
+            Some(e) => KeyMatch::Matched(e.clone()),
+            None if was_sequence => KeyMatch::Consumed,
+            None => KeyMatch::Unmatched,
         }
     }
 }
@@ -22,8 +111,10 @@ impl<E: Clone> KeyMap<E> {
 macro_rules! key_map {
     (
         $(
-            $([$($modifier:ident)+])?
-            $key:ident $(( $($arg:tt)* ))?
+            $(
+                $([$($modifier:ident)+])?
+                $key:ident $(( $($arg:tt)* ))?
+            )+
             => $e:expr
         ),*
         $(,)?
@@ -32,21 +123,38 @@ macro_rules! key_map {
         let mut map = HashMap::new();
 
         $(
-            let key = KeyEvent {
-                key_code: KeyCode::$key $(( $($arg)* ))?,
-                modifiers: Modifiers::empty() $($( | Modifiers::$modifier )*)?,
-            };
+            let keys = vec![$(
+                KeyEvent::new(
+                    KeyCode::$key $(( $($arg)* ))?,
+                    Modifiers::empty() $($( | Modifiers::$modifier )*)?,
+                )
+            ),+];
 
-            map.insert(key, $e);
+            map.insert(keys, $e);
         )*
 
-        KeyMap { map }
+        KeyMap::new(map)
     }};
 }
 
+const PARENS: ObjectKind = ObjectKind::Bracket('(', ')');
+const BRACKETS: ObjectKind = ObjectKind::Bracket('[', ']');
+const BRACES: ObjectKind = ObjectKind::Bracket('{', '}');
+const ANGLE_BRACKETS: ObjectKind = ObjectKind::Bracket('<', '>');
+
+fn inner(kind: ObjectKind) -> OperatorPendingEvent {
+    OperatorPendingEvent::TextObject(TextObject::inner(kind))
+}
+
+fn around(kind: ObjectKind) -> OperatorPendingEvent {
+    OperatorPendingEvent::TextObject(TextObject::around(kind))
+}
+
 pub struct KeyMaps {
     normal_mode: KeyMap<NormalModeEvent>,
-    insert_mode: KeyMap<InsertModeEvent<'static>>,
+    operator_pending: KeyMap<OperatorPendingEvent>,
+    visual_mode: KeyMap<VisualModeEvent>,
+    insert_mode: KeyMap<InsertModeEvent>,
     command_mode: KeyMap<CommandModeEvent>,
 
     editor_root: KeyMap<EditorRootEvent>,
@@ -55,18 +163,154 @@ pub struct KeyMaps {
 
 impl Default for KeyMaps {
     fn default() -> Self {
+        let motions = key_map! {
+            Left => Motion::Left,
+            Right => Motion::Right,
+            Up => Motion::Up,
+            Down => Motion::Down,
+            Char('h') => Motion::Left,
+            Char('l') => Motion::Right,
+            Char('k') => Motion::Up,
+            Char('j') => Motion::Down,
+            Char('g') Char('k') => Motion::DisplayUp,
+            Char('g') Char('j') => Motion::DisplayDown,
+            Char('g') Up => Motion::DisplayUp,
+            Char('g') Down => Motion::DisplayDown,
+
+            Home => Motion::LineStart,
+            Char('0') => Motion::LineStart,
+            Char('^') => Motion::FirstNonBlank,
+            End => Motion::LineEnd,
+            Char('$') => Motion::LineEnd,
+
+            Char('w') => Motion::WordForward,
+            Char('b') => Motion::WordBackward,
+            Char('e') => Motion::WordEnd,
+            Char('W') => Motion::BigWordForward,
+            Char('B') => Motion::BigWordBackward,
+            Char('E') => Motion::BigWordEnd,
+
+            Char('g') Char('g') => Motion::FileStart,
+            Char('G') => Motion::FileEnd,
+        };
+
         Self {
             normal_mode: key_map! {
                 Char('i') => NormalModeEvent::InsertMode,
+                Char('v') => NormalModeEvent::Visual,
+                Char('V') => NormalModeEvent::VisualLine,
+                [CTRL] Char('v') => NormalModeEvent::VisualBlock,
 
-                Up => NormalModeEvent::MoveUp,
-                Down => NormalModeEvent::MoveDown,
-                Left => NormalModeEvent::MoveLeft,
-                Right => NormalModeEvent::MoveRight,
+                Char('u') => NormalModeEvent::Undo,
+                [CTRL] Char('r') => NormalModeEvent::Redo,
 
-                Home => NormalModeEvent::MoveHome,
-                End => NormalModeEvent::MoveEnd,
-            },
+                PageUp => NormalModeEvent::PageUp,
+                PageDown => NormalModeEvent::PageDown,
+                [CTRL] Char('u') => NormalModeEvent::HalfPageUp,
+                [CTRL] Char('d') => NormalModeEvent::HalfPageDown,
+
+                Char('z') Char('z') => NormalModeEvent::ScrollCenter,
+                Char('z') Char('t') => NormalModeEvent::ScrollTop,
+                Char('z') Char('b') => NormalModeEvent::ScrollBottom,
+
+                Char('"') => NormalModeEvent::SelectRegister,
+                Char('d') => NormalModeEvent::Operator(Operator::Delete),
+                Char('c') => NormalModeEvent::Operator(Operator::Change),
+                Char('y') => NormalModeEvent::Operator(Operator::Yank),
+                Char('>') => NormalModeEvent::Operator(Operator::Indent),
+                Char('<') => NormalModeEvent::Operator(Operator::Dedent),
+                Char('g') Char('u') => NormalModeEvent::Operator(Operator::Lowercase),
+                Char('g') Char('U') => NormalModeEvent::Operator(Operator::Uppercase),
+                Char('=') => NormalModeEvent::Operator(Operator::Reindent),
+                Char('Y') => NormalModeEvent::YankLine,
+                Char('p') => NormalModeEvent::Put,
+                Char('P') => NormalModeEvent::PutBefore,
+            }
+            .with(&motions, NormalModeEvent::Motion),
+
+            operator_pending: key_map! {
+                Escape => OperatorPendingEvent::Escape,
+
+                Char('d') => OperatorPendingEvent::Operator(Operator::Delete),
+                Char('c') => OperatorPendingEvent::Operator(Operator::Change),
+                Char('y') => OperatorPendingEvent::Operator(Operator::Yank),
+                Char('>') => OperatorPendingEvent::Operator(Operator::Indent),
+                Char('<') => OperatorPendingEvent::Operator(Operator::Dedent),
+                Char('=') => OperatorPendingEvent::Operator(Operator::Reindent),
+                // both `guu` and `gugu`
+                Char('u') => OperatorPendingEvent::Operator(Operator::Lowercase),
+                Char('g') Char('u') => OperatorPendingEvent::Operator(Operator::Lowercase),
+                Char('U') => OperatorPendingEvent::Operator(Operator::Uppercase),
+                Char('g') Char('U') => OperatorPendingEvent::Operator(Operator::Uppercase),
+
+                Char('i') Char('w') => inner(ObjectKind::Word),
+                Char('a') Char('w') => around(ObjectKind::Word),
+                Char('i') Char('W') => inner(ObjectKind::BigWord),
+                Char('a') Char('W') => around(ObjectKind::BigWord),
+                Char('i') Char('p') => inner(ObjectKind::Paragraph),
+                Char('a') Char('p') => around(ObjectKind::Paragraph),
+
+                Char('i') Char('"') => inner(ObjectKind::Quote('"')),
+                Char('a') Char('"') => around(ObjectKind::Quote('"')),
+                Char('i') Char('\'') => inner(ObjectKind::Quote('\'')),
+                Char('a') Char('\'') => around(ObjectKind::Quote('\'')),
+                Char('i') Char('`') => inner(ObjectKind::Quote('`')),
+                Char('a') Char('`') => around(ObjectKind::Quote('`')),
+
+                Char('i') Char('(') => inner(PARENS),
+                Char('i') Char(')') => inner(PARENS),
+                Char('i') Char('b') => inner(PARENS),
+                Char('a') Char('(') => around(PARENS),
+                Char('a') Char(')') => around(PARENS),
+                Char('a') Char('b') => around(PARENS),
+                Char('i') Char('[') => inner(BRACKETS),
+                Char('i') Char(']') => inner(BRACKETS),
+                Char('a') Char('[') => around(BRACKETS),
+                Char('a') Char(']') => around(BRACKETS),
+                Char('i') Char('{') => inner(BRACES),
+                Char('i') Char('}') => inner(BRACES),
+                Char('i') Char('B') => inner(BRACES),
+                Char('a') Char('{') => around(BRACES),
+                Char('a') Char('}') => around(BRACES),
+                Char('a') Char('B') => around(BRACES),
+                Char('i') Char('<') => inner(ANGLE_BRACKETS),
+                Char('i') Char('>') => inner(ANGLE_BRACKETS),
+                Char('a') Char('<') => around(ANGLE_BRACKETS),
+                Char('a') Char('>') => around(ANGLE_BRACKETS),
+            }
+            .with(&motions, OperatorPendingEvent::Motion),
+
+            visual_mode: key_map! {
+                Char('v') => VisualModeEvent::Visual,
+                Char('V') => VisualModeEvent::VisualLine,
+                [CTRL] Char('v') => VisualModeEvent::VisualBlock,
+                Escape => VisualModeEvent::Escape,
+
+                PageUp => VisualModeEvent::PageUp,
+                PageDown => VisualModeEvent::PageDown,
+                [CTRL] Char('u') => VisualModeEvent::HalfPageUp,
+                [CTRL] Char('d') => VisualModeEvent::HalfPageDown,
+
+                Char('o') => VisualModeEvent::SwapEnds,
+
+                Char('"') => VisualModeEvent::SelectRegister,
+                Char('d') => VisualModeEvent::Operator(Operator::Delete),
+                Char('x') => VisualModeEvent::Operator(Operator::Delete),
+                Delete => VisualModeEvent::Operator(Operator::Delete),
+                Char('y') => VisualModeEvent::Operator(Operator::Yank),
+                Char('c') => VisualModeEvent::Operator(Operator::Change),
+                Char('s') => VisualModeEvent::Operator(Operator::Change),
+                Char('>') => VisualModeEvent::Operator(Operator::Indent),
+                Char('<') => VisualModeEvent::Operator(Operator::Dedent),
+                Char('u') => VisualModeEvent::Operator(Operator::Lowercase),
+                Char('U') => VisualModeEvent::Operator(Operator::Uppercase),
+                Char('g') Char('u') => VisualModeEvent::Operator(Operator::Lowercase),
+                Char('g') Char('U') => VisualModeEvent::Operator(Operator::Uppercase),
+                Char('=') => VisualModeEvent::Operator(Operator::Reindent),
+                Char('p') => VisualModeEvent::Put,
+                Char('P') => VisualModeEvent::PutKeep,
+            }
+            .with(&motions, VisualModeEvent::Motion),
 
             insert_mode: key_map! {
                 Delete => InsertModeEvent::Delete,
@@ -80,15 +324,20 @@ impl Default for KeyMaps {
                 Home => InsertModeEvent::MoveHome,
                 End => InsertModeEvent::MoveEnd,
 
+                PageUp => InsertModeEvent::PageUp,
+                PageDown => InsertModeEvent::PageDown,
+
                 Escape => InsertModeEvent::Escape,
             },
 
             command_mode: key_map! {
+                Return => CommandModeEvent::Execute,
                 Escape => CommandModeEvent::Escape,
             },
 
             editor_root: key_map! {
                 Char(':') => EditorRootEvent::CommandMode,
+                [CTRL] Char('z') => EditorRootEvent::Suspend,
                 Char('q') => EditorRootEvent::Quit,
             },
 
@@ -101,26 +350,48 @@ impl Default for KeyMaps {
 }
 
 impl KeyMaps {
-    pub fn normal_mode(&self, event: &Event) -> Option<NormalModeEvent> {
-        self.normal_mode.get(event)
+    pub fn normal_mode(
+        &self,
+        pending: &mut Vec<KeyEvent>,
+        event: &Event,
+    ) -> KeyMatch<NormalModeEvent> {
+        self.normal_mode.get_seq(pending, event)
     }
 
-    pub fn insert_mode<'a>(&self, event: &'a Event) -> Option<InsertModeEvent<'a>> {
-        self.insert_mode.get(event).or_else(|| match &event.kind {
-            EventKind::Key(KeyEvent {
-                key_code: KeyCode::Char(c),
-                modifiers,
-            }) if modifiers.is_empty() => Some(InsertModeEvent::InsertChar(*c)),
+    pub fn operator_pending(
+        &self,
+        pending: &mut Vec<KeyEvent>,
+        event: &Event,
+    ) -> KeyMatch<OperatorPendingEvent> {
+        self.operator_pending.get_seq(pending, event)
+    }
+
+    pub fn visual_mode(
+        &self,
+        pending: &mut Vec<KeyEvent>,
+        event: &Event,
+    ) -> KeyMatch<VisualModeEvent> {
+        self.visual_mode.get_seq(pending, event)
+    }
 
-            EventKind::Key(KeyEvent {
-                key_code: KeyCode::Return,
-                modifiers,
-            }) if modifiers.is_empty() => Some(InsertModeEvent::InsertChar('\n')),
+    pub fn insert_mode(&self, event: &Event) -> Option<InsertModeEvent> {
+        self.insert_mode
+            .get(event)
+            .or_else(|| match normalize(event)? {
+                KeyEvent {
+                    key_code: KeyCode::Char(c),
+                    modifiers,
+                    ..
+                } if modifiers.is_empty() => Some(InsertModeEvent::InsertChar(c)),
 
-            EventKind::String(s) => Some(InsertModeEvent::InsertString(s)),
+                KeyEvent {
+                    key_code: KeyCode::Return,
+                    modifiers,
+                    ..
+                } if modifiers.is_empty() => Some(InsertModeEvent::InsertChar('\n')),
 
-            _ => None,
-        })
+                _ => None,
+            })
     }
 
     pub fn command_mode(&self, event: &Event) -> Option<CommandModeEvent> {
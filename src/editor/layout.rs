@@ -0,0 +1,192 @@
+//! Where the graphemes of a line are shown, once tabs are expanded and
+//! control characters are given visible names.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::grapheme;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shown<'a> {
+    Text(&'a str),
+    /// Blank up to the next tab stop.
+    Tab,
+    /// Shown by its name, see [`control_name`].
+    Control(char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LaidOut<'a> {
+    /// The offset in chars from the start of the line.
+    pub offset: usize,
+    pub column: usize,
+    pub width: usize,
+
+    pub shown: Shown<'a>,
+}
+
+/// Where a screen row of a wrapped line starts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Row {
+    /// The offset in chars from the start of the line.
+    pub offset: usize,
+    pub column: usize,
+}
+
+/// Lay out the graphemes of a line, which shouldn't include its line break.
+pub fn layout(line: &str, tab_width: usize) -> impl Iterator<Item = LaidOut<'_>> {
+    let tab_width = tab_width.max(1);
+    let mut offset = 0;
+    let mut column = 0;
+
+    line.graphemes(true).map(move |grapheme| {
+        let (shown, width) = match grapheme.chars().next() {
+            Some('\t') => (Shown::Tab, tab_width - column % tab_width),
+            Some(c) if c.is_control() => (Shown::Control(c), control_name(c).len()),
+            _ => (Shown::Text(grapheme), grapheme::width(grapheme)),
+        };
+
+        let laid_out = LaidOut {
+            offset,
+            column,
+            width,
+            shown,
+        };
+
+        offset += grapheme.chars().count();
+        column += width;
+
+        laid_out
+    })
+}
+
+/// How many columns a line takes up.
+pub fn line_width(line: &str, tab_width: usize) -> usize {
+    layout(line, tab_width)
+        .last()
+        .map_or(0, |laid_out| laid_out.column + laid_out.width)
+}
+
+/// Split a line into rows that fit in `width` columns. Every row but the
+/// first starts with an indicator `indicator_width` columns wide.
+///
+/// With `linebreak`, rows are broken after whitespace where possible,
+/// rather than in the middle of a word.
+pub fn wrap(
+    line: &str,
+    tab_width: usize,
+    width: usize,
+    indicator_width: usize,
+    linebreak: bool,
+) -> Vec<Row> {
+    let mut rows = vec![Row::default()];
+    // where the last whitespace ends
+    let mut break_at = None;
+
+    for laid_out in layout(line, tab_width) {
+        loop {
+            let row = *rows.last().unwrap();
+            let width = match rows.len() {
+                1 => width,
+                _ => width.saturating_sub(indicator_width),
+            };
+
+            // Something has to go on every row, even if it doesn't fit.
+            if laid_out.column + laid_out.width <= row.column + width.max(1)
+                || laid_out.column == row.column
+            {
+                break;
+            }
+
+            rows.push(match break_at.take() {
+                Some(break_at @ Row { column, .. }) if linebreak && column > row.column => break_at,
+                _ => Row {
+                    offset: laid_out.offset,
+                    column: laid_out.column,
+                },
+            });
+        }
+
+        if let Shown::Tab | Shown::Text(" ") = laid_out.shown {
+            break_at = Some(Row {
+                offset: laid_out.offset + 1,
+                column: laid_out.column + laid_out.width,
+            });
+        }
+    }
+
+    rows
+}
+
+/// The offset in chars of the grapheme that covers `column`, or the length
+/// of the line in chars if it is too short.
+pub fn offset_at_column(line: &str, column: usize, tab_width: usize) -> usize {
+    layout(line, tab_width)
+        .find(|laid_out| laid_out.column + laid_out.width > column)
+        .map_or(line.chars().count(), |laid_out| laid_out.offset)
+}
+
+/// The name a control character is shown by: `^M` for those that have a
+/// Ctrl key, and the hex code like `<00>` for the rest.
+pub fn control_name(c: char) -> String {
+    match c {
+        '\x01'..='\x1f' => format!("^{}", (c as u8 + 0x40) as char),
+        '\x7f' => "^?".to_owned(),
+        _ => format!("<{:02x}>", c as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tabs_and_controls() {
+        let line = "a\tb\r\0漢\t";
+        let laid_out: Vec<_> = layout(line, 4)
+            .map(|laid_out| (laid_out.column, laid_out.width, laid_out.shown))
+            .collect();
+
+        assert_eq!(
+            laid_out,
+            [
+                (0, 1, Shown::Text("a")),
+                (1, 3, Shown::Tab),
+                (4, 1, Shown::Text("b")),
+                (5, 2, Shown::Control('\r')),
+                (7, 4, Shown::Control('\0')),
+                (11, 2, Shown::Text("漢")),
+                (13, 3, Shown::Tab),
+            ]
+        );
+
+        assert_eq!(line_width(line, 4), 16);
+        assert_eq!(line_width("\t\t", 8), 16);
+        assert_eq!(offset_at_column(line, 3, 4), 1);
+        assert_eq!(offset_at_column(line, 12, 4), 5);
+        assert_eq!(offset_at_column(line, 16, 4), 7);
+
+        let offsets =
+            |rows: Vec<Row>| -> Vec<usize> { rows.iter().map(|row| row.offset).collect() };
+        assert_eq!(offsets(wrap("", 4, 10, 0, false)), [0]);
+        assert_eq!(offsets(wrap("abcdefghij", 4, 10, 0, false)), [0]);
+        assert_eq!(offsets(wrap("abcdefghijk", 4, 4, 0, false)), [0, 4, 8]);
+        // the indicator takes up part of the later rows
+        assert_eq!(
+            offsets(wrap("abcdefghijk", 4, 4, 2, false)),
+            [0, 4, 6, 8, 10]
+        );
+        assert_eq!(offsets(wrap("a漢字", 4, 2, 0, false)), [0, 1, 2]);
+        assert_eq!(
+            offsets(wrap("ab cd efg hijklmn", 4, 7, 0, true)),
+            [0, 6, 10]
+        );
+        assert_eq!(
+            offsets(wrap("ab cd efg hijklmn", 4, 7, 0, false)),
+            [0, 7, 14]
+        );
+
+        assert_eq!(control_name('\x1b'), "^[");
+        assert_eq!(control_name('\x7f'), "^?");
+        assert_eq!(control_name('\u{85}'), "<85>");
+    }
+}
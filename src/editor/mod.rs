@@ -1,16 +1,153 @@
 mod editor_root;
 
+mod clipboard;
+pub mod command;
+mod config;
+mod document;
 mod event;
+mod grammar;
+mod gutter;
+mod history;
 mod keymaps;
+mod layout;
+mod motion;
 mod pane;
+mod register;
+mod selection;
 mod text_field;
+mod viewport;
 mod vsplit;
 
+pub use clipboard::{Clipboard, ClipboardCommands};
+pub use config::Config;
+pub use document::Document;
 pub use editor_root::EditorRoot;
+pub use gutter::{LineNumbers, Sign, Signs};
+pub use register::{Register, RegisterKind, Registers};
+pub use vsplit::VSplit;
 
+use self::command::*;
 use self::keymaps::*;
+use self::pane::Pane;
+use crate::ui::ControlFlow;
 
-#[derive(Default)]
 pub struct EditorState {
+    pub config: Config,
     pub key_maps: KeyMaps,
+    pub commands: CommandRegistry,
+
+    pub registers: Registers,
+
+    message: Option<Message>,
+}
+
+impl Default for EditorState {
+    fn default() -> Self {
+        let mut commands = CommandRegistry::default();
+        Self::register_commands(&mut commands);
+        Pane::register_commands(&mut commands);
+
+        let config = Config::default();
+        let clipboard_commands = config
+            .clipboard_commands
+            .clone()
+            .or_else(ClipboardCommands::detect);
+        let clipboard = Clipboard::new(config.osc52, clipboard_commands);
+
+        Self {
+            config,
+            key_maps: KeyMaps::default(),
+            commands,
+
+            registers: Registers::new(clipboard),
+
+            message: None,
+        }
+    }
+}
+
+impl EditorState {
+    fn register_commands(commands: &mut CommandRegistry) {
+        commands.register(
+            "ec[ho]",
+            CommandFlags::empty(),
+            CommandHandler::State(|state, cmd| {
+                state.show_message(cmd.args);
+                Ok(ControlFlow::Continue)
+            }),
+        );
+
+        for name in ["sus[pend]", "st[op]"] {
+            commands.register(
+                name,
+                CommandFlags::BANG,
+                CommandHandler::State(|_, _| Ok(ControlFlow::Suspend)),
+            );
+        }
+
+        for name in ["reg[isters]", "di[splay]"] {
+            commands.register(
+                name,
+                CommandFlags::empty(),
+                CommandHandler::State(|state, cmd| {
+                    state.show_registers(cmd.args);
+                    Ok(ControlFlow::Continue)
+                }),
+            );
+        }
+    }
+
+    /// List the registers that hold something, or just those named in
+    /// `names`, one per line.
+    fn show_registers(&mut self, names: &str) {
+        let mut text = String::from("Type Name Content");
+        for (name, register) in self.registers.iter() {
+            if !names.is_empty() && !names.contains(name) {
+                continue;
+            }
+
+            let content: String = register
+                .text
+                .chars()
+                .map(|c| match c.is_control() {
+                    true => layout::control_name(c),
+                    false => c.to_string(),
+                })
+                .collect();
+            text.push_str(&format!(
+                "\n  {}  \"{name}   {content}",
+                register.kind.letter()
+            ));
+        }
+        self.show_message(text);
+    }
+
+    pub fn show_message(&mut self, text: impl Into<String>) {
+        self.message = Some(Message {
+            text: text.into(),
+            is_error: false,
+        });
+    }
+
+    pub fn show_error(&mut self, text: impl Into<String>) {
+        self.message = Some(Message {
+            text: text.into(),
+            is_error: true,
+        });
+    }
+
+    /// Clear the message, returning whether there was one.
+    pub fn clear_message(&mut self) -> bool {
+        self.message.take().is_some()
+    }
+
+    pub fn message(&self) -> Option<&Message> {
+        self.message.as_ref()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub text: String,
+    pub is_error: bool,
 }
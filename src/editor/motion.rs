@@ -0,0 +1,446 @@
+//! Where motions move the cursor to, and what text objects cover.
+
+use std::ops::Range;
+
+use ropey::Rope;
+
+use super::selection::SelectionShape;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// By screen row rather than by line, when lines are wrapped.
+    DisplayUp,
+    DisplayDown,
+
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+
+    /// Words are runs of letters, digits and underscores, or of other
+    /// non-blank characters, and big words are runs of any non-blank
+    /// characters.
+    WordForward,
+    WordBackward,
+    WordEnd,
+    BigWordForward,
+    BigWordBackward,
+    BigWordEnd,
+
+    /// To the first or last line, or to the line given by the count.
+    FileStart,
+    FileEnd,
+}
+
+/// How much of the text between the cursor and where a motion goes an
+/// operator applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionKind {
+    /// Up to but not including where it goes.
+    Exclusive,
+    /// Up to and including the grapheme it goes to.
+    Inclusive,
+    /// Every line from the cursor's to the one it goes to.
+    Linewise,
+}
+
+impl Motion {
+    pub fn kind(self) -> MotionKind {
+        match self {
+            Self::Up | Self::Down | Self::FileStart | Self::FileEnd => MotionKind::Linewise,
+            Self::WordEnd | Self::BigWordEnd => MotionKind::Inclusive,
+            _ => MotionKind::Exclusive,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Word,
+    BigWord,
+    /// Lines up to the next blank line.
+    Paragraph,
+    /// Between a pair of the quote on the cursor line.
+    Quote(char),
+    /// Between a pair of brackets, which may be on different lines.
+    Bracket(char, char),
+}
+
+/// Text around the cursor, like a word or what is in parentheses, which
+/// is "inner" or "a" as in `iw` and `aw`. "A" objects take the blanks
+/// after them, or the brackets or quotes around them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextObject {
+    pub kind: ObjectKind,
+    pub around: bool,
+}
+
+impl TextObject {
+    pub fn inner(kind: ObjectKind) -> Self {
+        Self {
+            kind,
+            around: false,
+        }
+    }
+
+    pub fn around(kind: ObjectKind) -> Self {
+        Self { kind, around: true }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn char_class(c: char, big: bool) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if big || c == '_' || !c.is_ascii_punctuation() {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// Whether an empty line starts at `pos`, which word motions stop at.
+fn is_empty_line(rope: &Rope, pos: usize) -> bool {
+    rope.get_char(pos) == Some('\n') && (pos == 0 || rope.char(pos - 1) == '\n')
+}
+
+/// The start of the next word.
+pub fn word_forward(rope: &Rope, pos: usize, big: bool) -> usize {
+    let len = rope.len_chars();
+    if pos >= len {
+        return len;
+    }
+
+    let class = |pos| char_class(rope.char(pos), big);
+    let start_class = class(pos);
+
+    let mut next = pos;
+    if start_class != CharClass::Blank {
+        while next < len && class(next) == start_class {
+            next += 1;
+        }
+    }
+    while next < len && class(next) == CharClass::Blank {
+        if next > pos && is_empty_line(rope, next) {
+            break;
+        }
+        next += 1;
+    }
+    next
+}
+
+/// The start of the word before, or of the one the cursor is in.
+pub fn word_backward(rope: &Rope, pos: usize, big: bool) -> usize {
+    if pos == 0 {
+        return 0;
+    }
+
+    let class = |pos| char_class(rope.char(pos), big);
+
+    let mut prev = pos - 1;
+    while prev > 0 && class(prev) == CharClass::Blank && !is_empty_line(rope, prev) {
+        prev -= 1;
+    }
+    if class(prev) == CharClass::Blank {
+        return prev;
+    }
+
+    let word_class = class(prev);
+    while prev > 0 && class(prev - 1) == word_class {
+        prev -= 1;
+    }
+    prev
+}
+
+/// The last character of the word, or of the next one if already there.
+pub fn word_end(rope: &Rope, pos: usize, big: bool) -> usize {
+    let len = rope.len_chars();
+    let class = |pos| char_class(rope.char(pos), big);
+
+    let mut next = pos + 1;
+    while next < len && class(next) == CharClass::Blank {
+        next += 1;
+    }
+    if next >= len {
+        return len;
+    }
+
+    let word_class = class(next);
+    while next + 1 < len && class(next + 1) == word_class {
+        next += 1;
+    }
+    next
+}
+
+/// What a text object covers around `pos`, if anything.
+pub fn text_object(
+    rope: &Rope,
+    pos: usize,
+    object: TextObject,
+    count: usize,
+) -> Option<SelectionShape> {
+    match object.kind {
+        ObjectKind::Word | ObjectKind::BigWord => {
+            let big = object.kind == ObjectKind::BigWord;
+            let line_y = rope.char_to_line(pos);
+            let line_start = rope.line_to_char(line_y);
+            let line_end = line_start + line_len(rope, line_y);
+            if pos >= line_end {
+                return None;
+            }
+
+            let range = select_runs(
+                pos,
+                line_start..line_end,
+                count,
+                object.around,
+                |pos| char_class(rope.char(pos), big),
+                CharClass::Blank,
+            );
+            Some(SelectionShape::Chars(range))
+        }
+
+        ObjectKind::Paragraph => {
+            let is_blank = |line_y| rope.line(line_y).chars().all(char::is_whitespace);
+            let lines = select_runs(
+                rope.char_to_line(pos),
+                0..rope.len_lines(),
+                count,
+                object.around,
+                is_blank,
+                true,
+            );
+            Some(SelectionShape::Lines(lines.start..=(lines.end - 1)))
+        }
+
+        ObjectKind::Quote(quote) => quote_object(rope, pos, quote, object.around),
+
+        ObjectKind::Bracket(open, close) => {
+            let open_pos = find_open(rope, pos, open, close, count)?;
+            let close_pos = find_close(rope, open_pos, open, close)?;
+            if object.around {
+                return Some(SelectionShape::Chars(open_pos..(close_pos + 1)));
+            }
+
+            // Brackets on lines of their own leave whole lines between
+            // them.
+            let open_y = rope.char_to_line(open_pos);
+            let close_y = rope.char_to_line(close_pos);
+            let close_line_start = rope.line_to_char(close_y);
+            if rope.char(open_pos + 1) == '\n'
+                && close_y > open_y + 1
+                && rope
+                    .slice(close_line_start..close_pos)
+                    .chars()
+                    .all(char::is_whitespace)
+            {
+                return Some(SelectionShape::Lines((open_y + 1)..=(close_y - 1)));
+            }
+
+            Some(SelectionShape::Chars((open_pos + 1)..close_pos))
+        }
+    }
+}
+
+/// The length of a line without its line break.
+fn line_len(rope: &Rope, line_y: usize) -> usize {
+    let line = rope.line(line_y);
+    let len = line.len_chars();
+    match len > 0 && line.char(len - 1) == '\n' {
+        true => len - 1,
+        false => len,
+    }
+}
+
+/// Select `count` runs of items of the same class around `pos`, like
+/// words and the blanks between them. "A" objects take a run of blanks
+/// with each run, and if there are none after the last, the ones before
+/// the first.
+fn select_runs<T: PartialEq>(
+    pos: usize,
+    bounds: Range<usize>,
+    count: usize,
+    around: bool,
+    class: impl Fn(usize) -> T,
+    blank: T,
+) -> Range<usize> {
+    let run_end = |start: usize| {
+        let start_class = class(start);
+        let mut end = start + 1;
+        while end < bounds.end && class(end) == start_class {
+            end += 1;
+        }
+        end
+    };
+
+    let pos_class = class(pos);
+    let mut start = pos;
+    while start > bounds.start && class(start - 1) == pos_class {
+        start -= 1;
+    }
+
+    let mut end = start;
+    for _ in 0..count.max(1) {
+        if end >= bounds.end {
+            break;
+        }
+        end = run_end(end);
+        if around && end < bounds.end {
+            end = run_end(end);
+        }
+    }
+
+    if around && pos_class != blank && class(end - 1) != blank {
+        while start > bounds.start && class(start - 1) == blank {
+            start -= 1;
+        }
+    }
+    start..end
+}
+
+fn quote_object(rope: &Rope, pos: usize, quote: char, around: bool) -> Option<SelectionShape> {
+    let line_y = rope.char_to_line(pos);
+    let line_start = rope.line_to_char(line_y);
+    let line_end = line_start + line_len(rope, line_y);
+
+    // Quotes pair up from the start of the line, skipping escaped ones.
+    let mut quotes = vec![];
+    let mut escaped = false;
+    for (i, c) in rope.slice(line_start..line_end).chars().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => quotes.push(line_start + i),
+            _ => {}
+        }
+    }
+
+    // The pair around the cursor, or else the next one.
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| close >= pos)?;
+
+    if !around {
+        return Some(SelectionShape::Chars((open + 1)..close));
+    }
+
+    let is_blank = |pos| matches!(rope.char(pos), ' ' | '\t');
+    let mut start = open;
+    let mut end = close + 1;
+    while end < line_end && is_blank(end) {
+        end += 1;
+    }
+    if end == close + 1 {
+        while start > line_start && is_blank(start - 1) {
+            start -= 1;
+        }
+    }
+    Some(SelectionShape::Chars(start..end))
+}
+
+/// The opening bracket of the `count`th pair the cursor is in, counting
+/// a bracket under the cursor as inside its pair.
+fn find_open(rope: &Rope, pos: usize, open: char, close: char, count: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut found = 0;
+
+    let start = pos.min(rope.len_chars().checked_sub(1)?);
+    for i in (0..=start).rev() {
+        let c = rope.char(i);
+        if c == close && i != pos {
+            depth += 1;
+        } else if c == open {
+            if depth == 0 {
+                found += 1;
+                if found == count.max(1) {
+                    return Some(i);
+                }
+            } else {
+                depth -= 1;
+            }
+        }
+    }
+    None
+}
+
+fn find_close(rope: &Rope, open_pos: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in rope.chars_at(open_pos + 1).enumerate() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            if depth == 0 {
+                return Some(open_pos + 1 + i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::{text_object, word_backward, word_end, word_forward, ObjectKind, TextObject};
+    use crate::editor::selection::SelectionShape;
+
+    #[test]
+    fn motions_and_objects() {
+        let rope = Rope::from_str("foo.bar  baz\n\n  (a, (b))\n{\n  x\n}\n");
+
+        assert_eq!(word_forward(&rope, 0, false), 3);
+        assert_eq!(word_forward(&rope, 0, true), 9);
+        // stopping at the empty line
+        assert_eq!(word_forward(&rope, 9, false), 13);
+        assert_eq!(word_forward(&rope, 13, false), 16);
+        assert_eq!(word_backward(&rope, 16, false), 13);
+        assert_eq!(word_backward(&rope, 9, false), 4);
+        assert_eq!(word_end(&rope, 0, false), 2);
+        assert_eq!(word_end(&rope, 2, false), 3);
+        assert_eq!(word_end(&rope, 2, true), 6);
+
+        let object = |pos, object, count| text_object(&rope, pos, object, count);
+        let chars = |range| Some(SelectionShape::Chars(range));
+
+        let word = ObjectKind::Word;
+        assert_eq!(object(5, TextObject::inner(word), 1), chars(4..7));
+        assert_eq!(object(5, TextObject::around(word), 1), chars(4..9));
+        // no blanks after, so it takes the ones before
+        assert_eq!(object(10, TextObject::around(word), 1), chars(7..12));
+        assert_eq!(object(0, TextObject::inner(word), 3), chars(0..7));
+
+        let parens = ObjectKind::Bracket('(', ')');
+        assert_eq!(object(21, TextObject::inner(parens), 1), chars(21..22));
+        assert_eq!(object(21, TextObject::inner(parens), 2), chars(17..23));
+        assert_eq!(object(16, TextObject::around(parens), 1), chars(16..24));
+        let braces = ObjectKind::Bracket('{', '}');
+        assert_eq!(
+            object(28, TextObject::inner(braces), 1),
+            Some(SelectionShape::Lines(4..=4))
+        );
+
+        let paragraph = ObjectKind::Paragraph;
+        assert_eq!(
+            object(0, TextObject::around(paragraph), 1),
+            Some(SelectionShape::Lines(0..=1))
+        );
+
+        let rope = Rope::from_str(r#"say "a \" b" and "c""#);
+        let object = |pos, object| text_object(&rope, pos, object, 1);
+        let quote = ObjectKind::Quote('"');
+        assert_eq!(object(6, TextObject::inner(quote)), chars(5..11));
+        assert_eq!(object(0, TextObject::around(quote)), chars(4..13));
+        assert_eq!(object(14, TextObject::inner(quote)), chars(18..19));
+    }
+}
@@ -1,111 +1,550 @@
-use ropey::Rope;
+use std::borrow::Cow;
+use std::io;
+use std::ops::{Range, RangeInclusive};
+use std::path::PathBuf;
 
-use super::event::{InsertModeEvent, NormalModeEvent};
-use super::EditorState;
+use super::command::*;
+use super::document::Document;
+use super::event::{InsertModeEvent, NormalModeEvent, VisualModeEvent};
+use super::grammar::{self, Action, Grammar, NormalCommand, Operator, Target};
+use super::gutter::Gutter;
+use super::keymaps::KeyMatch;
+use super::layout::{self, Row, Shown};
+use super::motion::{self, Motion, MotionKind};
+use super::register::{Register, RegisterKind, Registers};
+use super::selection::{Selection, SelectionShape};
+use super::viewport::Viewport;
+use super::{Config, EditorState};
 use crate::event::*;
+use crate::grapheme;
+use crate::style::{Attributes, Color, Style, Underline, Weight};
 use crate::ui::*;
 
+/// The most text, in bytes, that a put with a count makes.
+const MAX_PUT_LEN: usize = 1 << 26;
+
+const SELECTION_STYLE: Style = Style {
+    fg: Color::Black,
+    bg: Color::White,
+    weight: Weight::Normal,
+    underline: Underline::None,
+    underline_color: Color::Default,
+    attributes: Attributes::empty(),
+};
+
+/// The style of control characters, which are shown by name.
+const CONTROL_STYLE: Style = Style {
+    fg: Color::Blue,
+    bg: Color::Default,
+    weight: Weight::Normal,
+    underline: Underline::None,
+    underline_color: Color::Default,
+    attributes: Attributes::empty(),
+};
+
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 enum Mode {
     #[default]
     Normal,
 
     Insert,
+
+    Visual,
+    VisualLine,
+    VisualBlock,
+}
+
+impl Mode {
+    fn is_visual(self) -> bool {
+        matches!(self, Self::Visual | Self::VisualLine | Self::VisualBlock)
+    }
+}
+
+/// Text typed after changing a block, which is copied to the block's other
+/// lines when leaving insert mode.
+#[derive(Debug, Clone)]
+struct BlockInsert {
+    /// Where the text is typed.
+    start: usize,
+    /// The lines it is copied to, and where in them.
+    lines: RangeInclusive<usize>,
+    column: usize,
 }
 
 pub struct Pane {
-    rope: Rope,
+    doc: Document,
 
     cursor_pos: usize,
     cursor_ghost_pos: usize,
 
+    /// The selection in the visual modes, whose head follows the cursor.
+    selection: Option<Selection>,
+    block_insert: Option<BlockInsert>,
+
+    viewport: Viewport,
+    /// Copied from the state so that cursor motion doesn't need it.
+    config: Config,
+
     mode: Mode,
+    /// Reads commands in normal mode.
+    grammar: Grammar,
+    pending_keys: Vec<KeyEvent>,
+
+    /// The register picked with `"` for the next command, and whether its
+    /// name is still to be typed.
+    register_name: Option<char>,
+    awaiting_register: bool,
+    /// What has been typed since entering insert mode.
+    inserted: String,
 }
 
 impl Default for Pane {
     fn default() -> Self {
-        Self {
-            rope: Rope::new(),
-
-            cursor_pos: 0,
-            cursor_ghost_pos: 0,
-
-            mode: Mode::Normal,
-        }
+        Self::new(Document::new())
     }
 }
 
 impl Widget<EditorState> for Pane {
     fn handle_event(&mut self, state: &mut EditorState, event: &Event) -> Option<ControlFlow> {
+        self.config.clone_from(&state.config);
+
+        if let EventKind::Mouse {
+            kind, button, x, y, ..
+        } = event.kind
+        {
+            return self.handle_mouse_event(state, kind, button, x, y);
+        }
+
+        match event.kind {
+            EventKind::FocusLost => {
+                self.autosave(state);
+                return Some(ControlFlow::Continue);
+            }
+            EventKind::FocusGained => {
+                self.check_disk(state);
+                return Some(ControlFlow::Continue);
+            }
+            _ => {}
+        }
+
+        if let EventKind::Paste(text) = &event.kind {
+            // Pasted text is typed in, so outside insert mode it starts an
+            // insert at the cursor as `i` would, dropping any unfinished
+            // command.
+            if self.mode != Mode::Insert {
+                self.exit_visual();
+                self.grammar.reset();
+                self.pending_keys.clear();
+                self.awaiting_register = false;
+                self.register_name = None;
+                self.handle_normal_mode_event(state, NormalModeEvent::InsertMode, None);
+            }
+            self.paste(text);
+            return Some(ControlFlow::Continue);
+        }
+
+        if self.awaiting_register {
+            return self.select_register(state, event);
+        }
+
         match self.mode {
-            Mode::Normal => state.key_maps.normal_mode(event).map(|event| {
-                self.handle_normal_mode_event(event);
-                ControlFlow::Continue
-            }),
+            Mode::Normal => match self.grammar.handle(&state.key_maps, event) {
+                KeyMatch::Matched(command) => {
+                    self.handle_normal_command(state, command);
+                    Some(ControlFlow::Continue)
+                }
+                KeyMatch::Consumed => Some(ControlFlow::Continue),
+                KeyMatch::Unmatched => None,
+            },
+
+            Mode::Visual | Mode::VisualLine | Mode::VisualBlock => {
+                match state.key_maps.visual_mode(&mut self.pending_keys, event) {
+                    KeyMatch::Matched(event) => {
+                        self.handle_visual_mode_event(state, event);
+                        Some(ControlFlow::Continue)
+                    }
+                    KeyMatch::Consumed => Some(ControlFlow::Continue),
+                    KeyMatch::Unmatched => None,
+                }
+            }
 
             Mode::Insert => state.key_maps.insert_mode(event).map(|event| {
-                self.handle_insert_mode_event(event);
+                self.handle_insert_mode_event(state, event);
                 ControlFlow::Continue
             }),
         }
     }
 
-    fn update(&mut self, _state: &mut EditorState) -> ControlFlow {
-        ControlFlow::Continue
+    fn update(&mut self, _state: &mut EditorState) -> Update {
+        Update::default()
     }
 
-    fn render(&mut self, buf: &mut crate::buffer::Buffer) {
-        for (y, line) in (0..buf.height()).zip(self.rope.lines()) {
-            for (x, c) in (0..buf.width()).zip(line.chars()) {
-                buf[[x, y]].c = c;
-            }
+    fn render(&mut self, state: &EditorState, buf: &mut crate::buffer::Buffer) {
+        self.config.clone_from(&state.config);
+        let cursor = self.pos_to_xy(self.cursor_pos).unwrap();
+
+        let gutter_width = self.gutter().width().min(buf.width());
+        self.viewport
+            .resize(buf.width() - gutter_width, buf.height());
+        let cursor_row = self.cursor_screen_row();
+        if self.config.wrap {
+            let mut viewport = self.viewport;
+            viewport.scroll_to_row(cursor.y, cursor_row.index, self.config.scrolloff, |y| {
+                self.line_rows(&self.line_text(y)).len()
+            });
+            self.viewport = viewport;
+        } else {
+            self.viewport
+                .scroll_to(cursor.x, cursor.y, self.config.scrolloff);
         }
 
-        let cursor = self.pos_to_xy(self.cursor_pos).unwrap();
-        if cursor.x < buf.width() && cursor.y < buf.height() {
-            buf.set_cursor(Some((cursor.x, cursor.y)));
+        let rope = self.doc.rope();
+        let gutter = self.gutter();
+        let text_x0 = gutter.width();
+        let indicator_width = self.indicator_width();
+        let selection = self.selection_shape();
+
+        let len_lines = rope.len_lines();
+        let top = self.viewport.top.min(len_lines - 1);
+        let mut screen_y = 0..buf.height();
+
+        // Each line is laid out once, then drawn a row at a time.
+        'lines: for line_y in top..len_lines {
+            let line = self.line_text(line_y);
+            let line_start = rope.line_to_char(line_y);
+            let rows = self.line_rows(&line);
+            let laid_out: Vec<_> = layout::layout(&line, self.config.tab_width).collect();
+
+            let skip = match line_y == top {
+                true => self.viewport.top_row,
+                false => 0,
+            };
+
+            for (index, row) in rows.iter().enumerate().skip(skip) {
+                let Some(y) = screen_y.next() else {
+                    break 'lines;
+                };
+
+                gutter.render(buf, y, line_y, index == 0);
+
+                let row_x0 = match index {
+                    0 => text_x0,
+                    _ => text_x0 + indicator_width,
+                };
+                let x0 = row_x0.min(buf.width());
+                if index > 0 {
+                    buf.set_str(gutter_width, y, &self.config.showbreak);
+                    for x in gutter_width..x0 {
+                        buf[[x, y]].style = CONTROL_STYLE;
+                    }
+                }
+
+                let left = row.column + self.viewport.left;
+                let row_start = laid_out.partition_point(|laid_out| laid_out.offset < row.offset);
+                let row_end = match rows.get(index + 1) {
+                    Some(next) => {
+                        laid_out.partition_point(|laid_out| laid_out.offset < next.offset)
+                    }
+                    None => laid_out.len(),
+                };
+
+                for laid_out in &laid_out[row_start..row_end] {
+                    let start = x0 + laid_out.column.saturating_sub(left);
+                    let end = (x0 + (laid_out.column + laid_out.width).saturating_sub(left))
+                        .min(buf.width());
+                    if start >= buf.width() {
+                        break;
+                    }
+
+                    // Anything but a tab that is cut off by the left edge is
+                    // left blank.
+                    let cut_off = laid_out.column < left;
+                    match laid_out.shown {
+                        Shown::Text(grapheme) if !cut_off => {
+                            buf.set_grapheme(start, y, grapheme);
+                        }
+                        Shown::Control(c) if !cut_off => {
+                            buf.set_str(start, y, &layout::control_name(c));
+                            for x in start..end {
+                                buf[[x, y]].style = CONTROL_STYLE;
+                            }
+                        }
+                        _ => {}
+                    }
+
+                    let pos = line_start + laid_out.offset;
+                    let columns = laid_out.column..(laid_out.column + laid_out.width);
+                    if selection
+                        .as_ref()
+                        .is_some_and(|selection| selection.contains(pos, line_y, columns))
+                    {
+                        for x in start..end {
+                            buf[[x, y]].style = SELECTION_STYLE;
+                        }
+                    }
+                }
+
+                if (line_y, index) == (cursor.y, cursor_row.index) {
+                    let x = row_x0 + cursor.x.wrapping_sub(left);
+                    if x < buf.width() {
+                        buf.set_cursor(Some((x, y)));
+                    }
+                }
+            }
         }
     }
 }
 
+/// A row on the screen: part of a line if lines are wrapped, or all of it
+/// if not.
+#[derive(Debug, Clone, Copy)]
+struct ScreenRow {
+    line_y: usize,
+    /// Which of the line's rows this is.
+    index: usize,
+    row: Row,
+    /// Where the next row of the line starts, if there is one.
+    next: Option<usize>,
+}
+
 impl Pane {
-    fn handle_normal_mode_event(&mut self, event: NormalModeEvent) {
+    pub fn new(doc: Document) -> Self {
+        Self {
+            doc,
+
+            cursor_pos: 0,
+            cursor_ghost_pos: 0,
+
+            selection: None,
+            block_insert: None,
+
+            viewport: Viewport::default(),
+            config: Config::default(),
+
+            mode: Mode::Normal,
+            grammar: Grammar::default(),
+            pending_keys: vec![],
+
+            register_name: None,
+            awaiting_register: false,
+            inserted: String::new(),
+        }
+    }
+
+    /// Replace the current document with the file at `path`.
+    pub fn open(&mut self, path: impl Into<PathBuf>) -> io::Result<()> {
+        *self = Self::new(Document::open(path)?);
+        Ok(())
+    }
+
+    /// The keys typed so far of an incomplete command, to show on screen.
+    pub fn pending_keys(&self) -> String {
+        if !self.mode.is_visual() {
+            return self.grammar.pending().to_string();
+        }
+
+        let mut keys = String::new();
+        if self.awaiting_register {
+            keys.push('"');
+        } else if let Some(name) = self.register_name {
+            keys.push('"');
+            keys.push(name);
+        }
+        for key_event in &self.pending_keys {
+            keys.push_str(&grammar::key_name(key_event));
+        }
+        keys
+    }
+
+    fn handle_normal_command(&mut self, state: &mut EditorState, command: NormalCommand) {
+        self.register_name = command.register;
+        match command.action {
+            Action::Event(event) => self.handle_normal_mode_event(state, event, command.count),
+            Action::Operator(operator, target) => {
+                self.operate(state, operator, target, command.count)
+            }
+        }
+        self.register_name = None;
+    }
+
+    fn handle_normal_mode_event(
+        &mut self,
+        state: &mut EditorState,
+        event: NormalModeEvent,
+        count: Option<usize>,
+    ) {
         match event {
-            NormalModeEvent::InsertMode => self.mode = Mode::Insert,
+            NormalModeEvent::InsertMode => {
+                self.doc.begin_transaction(self.cursor_pos);
+                self.block_insert = None;
+                self.enter_insert();
+            }
+
+            NormalModeEvent::Visual => self.enter_visual(Mode::Visual),
+            NormalModeEvent::VisualLine => self.enter_visual(Mode::VisualLine),
+            NormalModeEvent::VisualBlock => self.enter_visual(Mode::VisualBlock),
 
-            NormalModeEvent::MoveUp => self.move_cursor_vertical(-1),
-            NormalModeEvent::MoveDown => self.move_cursor_vertical(1),
-            NormalModeEvent::MoveLeft => self.move_cursor(-1),
-            NormalModeEvent::MoveRight => self.move_cursor(1),
+            NormalModeEvent::Undo | NormalModeEvent::Redo => {
+                for _ in 0..count.unwrap_or(1) {
+                    let res = match event {
+                        NormalModeEvent::Undo => self.undo(),
+                        _ => self.redo(),
+                    };
+                    if let Err(err) = res {
+                        state.show_error(err.to_string());
+                        break;
+                    }
+                }
+            }
+
+            NormalModeEvent::Motion(motion) => self.apply_motion(motion, count),
+
+            NormalModeEvent::PageUp => self.scroll_page(-1, state.config.scrolloff),
+            NormalModeEvent::PageDown => self.scroll_page(1, state.config.scrolloff),
+            NormalModeEvent::HalfPageUp => self.scroll_half_page(-1),
+            NormalModeEvent::HalfPageDown => self.scroll_half_page(1),
+
+            NormalModeEvent::ScrollCenter => self.viewport.center_on(self.cursor_y()),
+            NormalModeEvent::ScrollTop => self
+                .viewport
+                .top_on(self.cursor_y(), state.config.scrolloff),
+            NormalModeEvent::ScrollBottom => self
+                .viewport
+                .bottom_on(self.cursor_y(), state.config.scrolloff),
+
+            // read by the grammar as part of a command
+            NormalModeEvent::SelectRegister | NormalModeEvent::Operator(_) => {}
+
+            NormalModeEvent::YankLine => self.operate(state, Operator::Yank, Target::Line, count),
+            NormalModeEvent::Put => self.put(state, false, count),
+            NormalModeEvent::PutBefore => self.put(state, true, count),
+        }
+    }
 
-            NormalModeEvent::MoveHome => self.move_cursor_home(),
-            NormalModeEvent::MoveEnd => self.move_cursor_end(),
+    /// Read the name of the register after `"`.
+    fn select_register(&mut self, state: &mut EditorState, event: &Event) -> Option<ControlFlow> {
+        let EventKind::Key(key_event) = event.kind else {
+            return None;
+        };
+        if key_event.kind == KeyEventKind::Release {
+            return Some(ControlFlow::Continue);
         }
+
+        self.awaiting_register = false;
+        match key_event.key_code {
+            KeyCode::Char(name) if Registers::is_valid(name) => self.register_name = Some(name),
+            KeyCode::Char(name) => state.show_error(format!("Invalid register name: {name}")),
+            // e.g. escape, which cancels it
+            _ => {}
+        }
+        Some(ControlFlow::Continue)
     }
 
-    fn handle_insert_mode_event(&mut self, event: InsertModeEvent) {
+    fn handle_mouse_event(
+        &mut self,
+        state: &EditorState,
+        kind: MouseKind,
+        button: Option<MouseButton>,
+        x: usize,
+        y: usize,
+    ) -> Option<ControlFlow> {
+        let scroll = state.config.mouse_scroll as isize;
+
+        match (kind, button) {
+            (MouseKind::Press, Some(MouseButton::Left)) => {
+                self.grammar.reset();
+                self.exit_visual();
+                self.set_cursor(self.screen_to_pos(x, y));
+            }
+
+            // Dragging selects from where the button was pressed.
+            (MouseKind::Drag, Some(MouseButton::Left)) => {
+                if self.mode == Mode::Insert {
+                    self.doc.commit_transaction(self.cursor_pos);
+                }
+                if !self.mode.is_visual() {
+                    self.enter_visual(Mode::Visual);
+                }
+
+                self.set_cursor(self.screen_to_pos(x, y));
+                if let Some(selection) = &mut self.selection {
+                    selection.head = self.cursor_pos;
+                }
+            }
+
+            (MouseKind::ScrollUp, _) => self.scroll_view(-scroll, state.config.scrolloff),
+            (MouseKind::ScrollDown, _) => self.scroll_view(scroll, state.config.scrolloff),
+
+            _ => return None,
+        }
+
+        Some(ControlFlow::Continue)
+    }
+
+    fn handle_visual_mode_event(&mut self, state: &mut EditorState, event: VisualModeEvent) {
         match event {
-            InsertModeEvent::InsertChar(c) => {
-                self.rope.insert_char(self.cursor_pos, c);
-                self.move_cursor(1);
+            VisualModeEvent::Visual => self.switch_visual(Mode::Visual),
+            VisualModeEvent::VisualLine => self.switch_visual(Mode::VisualLine),
+            VisualModeEvent::VisualBlock => self.switch_visual(Mode::VisualBlock),
+            VisualModeEvent::Escape => self.exit_visual(),
+
+            VisualModeEvent::Motion(motion) => self.apply_motion(motion, None),
+
+            VisualModeEvent::PageUp => self.scroll_page(-1, state.config.scrolloff),
+            VisualModeEvent::PageDown => self.scroll_page(1, state.config.scrolloff),
+            VisualModeEvent::HalfPageUp => self.scroll_half_page(-1),
+            VisualModeEvent::HalfPageDown => self.scroll_half_page(1),
+
+            VisualModeEvent::SwapEnds => {
+                if let Some(selection) = self.selection {
+                    self.selection = Some(Selection::new(selection.head, selection.anchor));
+                    self.set_cursor(selection.anchor);
+                }
             }
 
-            InsertModeEvent::InsertString(s) => {
-                self.rope.insert(self.cursor_pos, s);
-                // conversion could *technically* overflow
-                self.move_cursor(s.chars().count() as isize);
+            VisualModeEvent::SelectRegister => {
+                self.awaiting_register = true;
+                return;
+            }
+            VisualModeEvent::Operator(operator) => {
+                if let Some(shape) = self.selection_shape() {
+                    self.exit_visual();
+                    self.apply_operator(state, operator, &shape);
+                }
+            }
+            VisualModeEvent::Put => self.put_selection(state, false),
+            VisualModeEvent::PutKeep => self.put_selection(state, true),
+        }
+
+        self.register_name = None;
+
+        if let Some(selection) = &mut self.selection {
+            selection.head = self.cursor_pos;
+        }
+    }
+
+    fn handle_insert_mode_event(&mut self, state: &mut EditorState, event: InsertModeEvent) {
+        match event {
+            InsertModeEvent::InsertChar(c) => {
+                self.doc.insert_char(self.cursor_pos, c);
+                self.inserted.push(c);
+                // not by a grapheme, as it could have combined with the
+                // one before
+                self.set_cursor(self.cursor_pos + 1);
             }
 
             InsertModeEvent::Delete => {
-                let _ = self
-                    .rope
-                    .try_remove(self.cursor_pos..(self.cursor_pos.saturating_add(1)));
+                let end = grapheme::next_boundary(self.doc.rope(), self.cursor_pos);
+                self.doc.remove(self.cursor_pos..end);
             }
 
             InsertModeEvent::Backspace => {
-                let new_pos = self.cursor_pos.saturating_sub(1);
-                let _ = self.rope.try_remove(new_pos..self.cursor_pos);
-                self.move_cursor(-1);
+                let new_pos = grapheme::prev_boundary(self.doc.rope(), self.cursor_pos);
+                self.doc.remove(new_pos..self.cursor_pos);
+                for _ in new_pos..self.cursor_pos {
+                    self.inserted.pop();
+                }
+                self.set_cursor(new_pos);
             }
 
             InsertModeEvent::MoveUp => self.move_cursor_vertical(-1),
@@ -116,73 +555,613 @@ impl Pane {
             InsertModeEvent::MoveHome => self.move_cursor_home(),
             InsertModeEvent::MoveEnd => self.move_cursor_end(),
 
-            InsertModeEvent::Escape => self.mode = Mode::Normal,
+            InsertModeEvent::PageUp => self.scroll_page(-1, state.config.scrolloff),
+            InsertModeEvent::PageDown => self.scroll_page(1, state.config.scrolloff),
+
+            InsertModeEvent::Escape => {
+                self.finish_block_insert();
+                self.doc.commit_transaction(self.cursor_pos);
+                state
+                    .registers
+                    .set_last_inserted(std::mem::take(&mut self.inserted));
+                self.mode = Mode::Normal;
+            }
+        }
+    }
+}
+
+impl Pane {
+    pub fn register_commands(commands: &mut CommandRegistry) {
+        commands.register("w[rite]", CommandFlags::BANG, CommandHandler::Widget);
+        commands.register("wq", CommandFlags::BANG, CommandHandler::Widget);
+        commands.register("x[it]", CommandFlags::BANG, CommandHandler::Widget);
+        commands.register("e[dit]", CommandFlags::BANG, CommandHandler::Widget);
+        commands.register("q[uit]", CommandFlags::BANG, CommandHandler::Widget);
+
+        commands.register("u[ndo]", CommandFlags::empty(), CommandHandler::Widget);
+        commands.register("red[o]", CommandFlags::empty(), CommandHandler::Widget);
+
+        commands.register("d[elete]", CommandFlags::RANGE, CommandHandler::Widget);
+        commands.register("s[ubstitute]", CommandFlags::RANGE, CommandHandler::Widget);
+    }
+
+    pub fn execute_command(
+        &mut self,
+        state: &mut EditorState,
+        cmd: &Command,
+    ) -> Option<CommandResult> {
+        let res = match cmd.name {
+            "" => self.goto_line(cmd),
+
+            "write" => self.write(state, cmd.args, cmd.bang),
+            "wq" => self
+                .write(state, cmd.args, cmd.bang)
+                .map(|_| ControlFlow::Exit),
+            // unlike :wq, :x only writes when there's something to write
+            "xit" if !self.doc.is_dirty() => Ok(ControlFlow::Exit),
+            "xit" => self
+                .write(state, cmd.args, cmd.bang)
+                .map(|_| ControlFlow::Exit),
+            "edit" => self.edit(state, cmd.args, cmd.bang),
+            "quit" => self.quit(cmd.bang),
+
+            "undo" => self.undo(),
+            "redo" => self.redo(),
+
+            "delete" => self.delete_lines(cmd),
+            "substitute" => self.substitute(state, cmd),
+
+            _ => return None,
+        };
+
+        Some(res)
+    }
+
+    /// Write the document, to `path` if given. Unless forced, a file that
+    /// changed on disk since it was read isn't overwritten.
+    fn write(&mut self, state: &mut EditorState, path: &str, force: bool) -> CommandResult {
+        if path.is_empty() && !force && self.doc.changed_on_disk() {
+            return Err(CommandError::new(format!(
+                "\"{}\" changed on disk since it was read (add ! to override)",
+                self.doc.display_name()
+            )));
+        }
+
+        let res = if path.is_empty() {
+            self.doc.save()
+        } else {
+            self.doc.save_as(path)
+        };
+
+        match res {
+            Ok(()) => {
+                let name = self.doc.display_name();
+                let lines = self.line_count();
+                state.show_message(format!("\"{name}\" {lines}L written"));
+                Ok(ControlFlow::Continue)
+            }
+            Err(err) if path.is_empty() => Err(CommandError::new(format!(
+                "\"{}\": {err}",
+                self.doc.display_name()
+            ))),
+            Err(err) => Err(CommandError::new(format!("\"{path}\": {err}"))),
+        }
+    }
+
+    /// Save the document if it has changed and autosave is on.
+    fn autosave(&mut self, state: &mut EditorState) {
+        if !state.config.autosave || self.doc.path().is_none() || !self.doc.is_dirty() {
+            return;
+        }
+
+        let name = self.doc.display_name();
+
+        // Don't overwrite what something else wrote.
+        if self.doc.changed_on_disk() {
+            state.show_error(format!("\"{name}\" changed on disk since it was read"));
+            return;
+        }
+
+        if let Err(err) = self.doc.save() {
+            state.show_error(format!("\"{name}\": {err}"));
+        }
+    }
+
+    /// Reload the document if its file changed on disk, or warn about it if
+    /// that would lose changes.
+    fn check_disk(&mut self, state: &mut EditorState) {
+        if !self.doc.changed_on_disk() {
+            return;
+        }
+
+        let name = self.doc.display_name();
+
+        if self.doc.is_dirty() || !state.config.autoread {
+            state.show_error(format!("\"{name}\" changed on disk since it was read"));
+            return;
+        }
+
+        // Insert mode keeps a transaction open, which the reload mustn't
+        // become part of, so it is closed around it.
+        let inserting = self.mode == Mode::Insert;
+        if inserting {
+            self.doc.commit_transaction(self.cursor_pos);
+            self.block_insert = None;
+        }
+        let res = self.doc.reload();
+        self.set_cursor(self.cursor_pos);
+        if inserting {
+            self.doc.begin_transaction(self.cursor_pos);
+        }
+
+        match res {
+            Ok(()) => {
+                let lines = self.line_count();
+                state.show_message(format!("\"{name}\" {lines}L reloaded"));
+            }
+            Err(err) => state.show_error(format!("\"{name}\": {err}")),
+        }
+    }
+
+    fn edit(&mut self, state: &mut EditorState, path: &str, force: bool) -> CommandResult {
+        if path.is_empty() {
+            return Err(CommandError::new("No file name"));
+        }
+
+        if self.doc.is_dirty() && !force {
+            return Err(CommandError::new(
+                "No write since last change (add ! to override)",
+            ));
+        }
+
+        self.open(path)
+            .map_err(|err| CommandError::new(format!("\"{path}\": {err}")))?;
+
+        let lines = self.line_count();
+        state.show_message(format!("\"{path}\" {lines}L"));
+
+        Ok(ControlFlow::Continue)
+    }
+
+    fn quit(&self, force: bool) -> CommandResult {
+        if self.doc.is_dirty() && !force {
+            Err(CommandError::new(
+                "No write since last change (add ! to override)",
+            ))
+        } else {
+            Ok(ControlFlow::Exit)
+        }
+    }
+
+    fn undo(&mut self) -> CommandResult {
+        let pos = self
+            .doc
+            .undo()
+            .ok_or_else(|| CommandError::new("Already at oldest change"))?;
+        self.set_cursor(pos);
+        Ok(ControlFlow::Continue)
+    }
+
+    fn redo(&mut self) -> CommandResult {
+        let pos = self
+            .doc
+            .redo()
+            .ok_or_else(|| CommandError::new("Already at newest change"))?;
+        self.set_cursor(pos);
+        Ok(ControlFlow::Continue)
+    }
+
+    /// The lines a command's range covers, the current line if it has none.
+    fn command_lines(&self, cmd: &Command) -> Result<RangeInclusive<usize>, CommandError> {
+        let line_count = self.line_count();
+        let current_line = self.cursor_y().min(line_count - 1);
+        cmd.line_range(current_line, line_count)
+    }
+
+    fn goto_line(&mut self, cmd: &Command) -> CommandResult {
+        let lines = self.command_lines(cmd)?;
+
+        self.set_cursor(self.doc.rope().line_to_char(*lines.end()));
+
+        Ok(ControlFlow::Continue)
+    }
+
+    fn delete_lines(&mut self, cmd: &Command) -> CommandResult {
+        let lines = self.command_lines(cmd)?;
+
+        self.doc.begin_transaction(self.cursor_pos);
+        self.remove_lines(lines);
+        self.doc.commit_transaction(self.cursor_pos);
+
+        Ok(ControlFlow::Continue)
+    }
+
+    /// Remove whole lines, moving the cursor to the line after them.
+    fn remove_lines(&mut self, lines: RangeInclusive<usize>) {
+        let rope = self.doc.rope();
+        let mut start = rope.line_to_char(*lines.start());
+        let end = rope.line_to_char(lines.end().saturating_add(1).min(rope.len_lines()));
+
+        // When deleting up to the end of the file, take the preceding
+        // newline with it.
+        if *lines.end() + 1 >= rope.len_lines() {
+            start = start.saturating_sub(1);
+        }
+
+        self.doc.remove(start..end);
+
+        let rope = self.doc.rope();
+        let line = (*lines.start()).min(rope.len_lines() - 1);
+        self.set_cursor(rope.line_to_char(line));
+    }
+
+    /// `:s/pattern/replacement/flags`, where the pattern is matched literally.
+    fn substitute(&mut self, state: &mut EditorState, cmd: &Command) -> CommandResult {
+        let mut chars = cmd.args.chars();
+        let Some(delim) = chars.next() else {
+            return Err(CommandError::new("Usage: s/pattern/replacement/[g]"));
+        };
+
+        let mut parts = chars.as_str().splitn(3, delim);
+        let pattern = parts.next().unwrap_or_default();
+        let replacement = parts.next().unwrap_or_default();
+        let global = parts.next().unwrap_or_default().contains('g');
+
+        if pattern.is_empty() {
+            return Err(CommandError::new("Empty pattern"));
+        }
+        state.registers.set_last_search(pattern);
+
+        let lines = self.command_lines(cmd)?;
+
+        self.doc.begin_transaction(self.cursor_pos);
+
+        let mut last_changed = None;
+        for y in lines {
+            let line_start = self.doc.rope().line_to_char(y);
+            let line = self.doc.rope().line(y).to_string();
+
+            let new_line = if global {
+                line.replace(pattern, replacement)
+            } else {
+                line.replacen(pattern, replacement, 1)
+            };
+
+            if new_line != line {
+                self.doc
+                    .remove(line_start..(line_start + line.chars().count()));
+                self.doc.insert(line_start, &new_line);
+                last_changed = Some(line_start);
+            }
+        }
+
+        if let Some(pos) = last_changed {
+            self.set_cursor(pos);
+        }
+        self.doc.commit_transaction(self.cursor_pos);
+
+        match last_changed {
+            Some(_) => Ok(ControlFlow::Continue),
+            None => Err(CommandError::new(format!("Pattern not found: {pattern}"))),
         }
     }
 }
 
 impl Pane {
+    /// The line and display column of a position.
     fn pos_to_xy(&self, pos: usize) -> Option<Pos> {
-        let y = self.rope.char_to_line(pos);
-        let line_start = self.rope.line_to_char(y);
-        let x = pos - line_start;
+        let y = self.doc.rope().char_to_line(pos);
+        let line_start = self.doc.rope().line_to_char(y);
+        let line = Cow::from(self.doc.rope().slice(line_start..pos));
+        let x = layout::line_width(&line, self.config.tab_width);
 
         Some(Pos { x, y })
     }
 
+    /// The position of the grapheme at a display column of a line, or the
+    /// end of the line if it is too short.
+    fn column_to_pos(&self, line_y: usize, column: usize) -> usize {
+        let line_start = self.doc.rope().line_to_char(line_y);
+        let line = self.line_text(line_y);
+        line_start + layout::offset_at_column(&line, column, self.config.tab_width)
+    }
+
+    /// The text of a line, without its line break.
+    fn line_text(&self, line_y: usize) -> Cow<'_, str> {
+        let line_len = self.line_len(line_y).unwrap_or(0);
+        Cow::from(self.doc.rope().line(line_y).slice(..line_len))
+    }
+
+    /// The rows a line is wrapped into, which is just the one if lines
+    /// aren't wrapped.
+    fn line_rows(&self, line: &str) -> Vec<Row> {
+        if !self.config.wrap {
+            return vec![Row::default()];
+        }
+
+        layout::wrap(
+            line,
+            self.config.tab_width,
+            self.viewport.width,
+            self.indicator_width(),
+            self.config.linebreak,
+        )
+    }
+
+    /// How much of the screen the wrap indicator takes up, leaving at least
+    /// one column for text.
+    fn indicator_width(&self) -> usize {
+        grapheme::str_width(&self.config.showbreak).min(self.viewport.width.saturating_sub(1))
+    }
+
+    fn gutter(&self) -> Gutter<'_> {
+        Gutter::new(
+            self.config.line_numbers,
+            self.doc.signs(),
+            self.doc.rope().len_lines(),
+            self.cursor_y(),
+        )
+    }
+
+    /// Where the text of a row starts on the screen, after the gutter and
+    /// any wrap indicator.
+    fn row_x0(&self, index: usize) -> usize {
+        match index {
+            0 => self.gutter().width(),
+            _ => self.gutter().width() + self.indicator_width(),
+        }
+    }
+
+    fn line_screen_rows(&self, line_y: usize) -> Vec<ScreenRow> {
+        let rows = self.line_rows(&self.line_text(line_y));
+        let nexts = rows
+            .iter()
+            .skip(1)
+            .map(|row| Some(row.offset))
+            .chain([None]);
+
+        rows.iter()
+            .zip(nexts)
+            .enumerate()
+            .map(|(index, (&row, next))| ScreenRow {
+                line_y,
+                index,
+                row,
+                next,
+            })
+            .collect()
+    }
+
+    /// The rows on the screen, from the top down.
+    fn screen_rows(&self) -> impl Iterator<Item = ScreenRow> + '_ {
+        let len_lines = self.doc.rope().len_lines();
+        let top = self.viewport.top.min(len_lines - 1);
+
+        (top..len_lines).flat_map(move |line_y| {
+            let skip = match line_y == top {
+                true => self.viewport.top_row,
+                false => 0,
+            };
+            self.line_screen_rows(line_y).into_iter().skip(skip)
+        })
+    }
+
+    /// The row of its line that the cursor is on.
+    fn cursor_screen_row(&self) -> ScreenRow {
+        let line_y = self.cursor_y();
+        let offset = self.cursor_pos - self.doc.rope().line_to_char(line_y);
+
+        let rows = self.line_screen_rows(line_y);
+        let index = rows
+            .iter()
+            .rposition(|screen_row| screen_row.row.offset <= offset)
+            .unwrap_or(0);
+        rows[index]
+    }
+
+    /// The position in the document that is shown at a column of a row, or
+    /// the last one in the row if the row is too short.
+    fn screen_row_to_pos(&self, screen_row: ScreenRow, x: usize) -> usize {
+        let column = screen_row.row.column
+            + self.viewport.left
+            + x.saturating_sub(self.row_x0(screen_row.index));
+        let pos = self.column_to_pos(screen_row.line_y, column);
+
+        match screen_row.next {
+            Some(next) => {
+                let rope = self.doc.rope();
+                let next = rope.line_to_char(screen_row.line_y) + next;
+                pos.min(grapheme::prev_boundary(rope, next))
+            }
+            None => pos,
+        }
+    }
+
     fn line_len(&self, line_y: usize) -> Option<usize> {
-        let line_len = self.rope.get_line(line_y)?.len_chars();
+        let line_len = self.doc.rope().get_line(line_y)?.len_chars();
 
         // There is always at least one line.
-        if line_y == self.rope.len_lines() - 1 {
+        if line_y == self.doc.rope().len_lines() - 1 {
             Some(line_len)
         } else {
             Some(line_len.saturating_sub(1))
         }
     }
 
+    /// The number of lines, not counting the empty line after a final line
+    /// break.
+    fn line_count(&self) -> usize {
+        let rope = self.doc.rope();
+        match rope.len_chars() > 0 && rope.char(rope.len_chars() - 1) == '\n' {
+            true => rope.len_lines() - 1,
+            false => rope.len_lines(),
+        }
+    }
+
+    fn cursor_y(&self) -> usize {
+        self.doc.rope().char_to_line(self.cursor_pos)
+    }
+
+    /// Scroll by a page, moving the cursor to stay on screen.
+    fn scroll_page(&mut self, dir: isize, scrolloff: usize) {
+        let amount = self.viewport.height.saturating_sub(2).max(1);
+        self.scroll_view(dir * amount as isize, scrolloff);
+    }
+
+    /// Scroll by a number of lines, moving the cursor only if it would
+    /// otherwise go off screen.
+    fn scroll_view(&mut self, lines: isize, scrolloff: usize) {
+        self.viewport.scroll_by(lines, self.doc.rope().len_lines());
+
+        let scrolloff = scrolloff.min(self.viewport.height.saturating_sub(1) / 2);
+        let min_y = self.viewport.top + scrolloff;
+        let max_y = (self.viewport.top + self.viewport.height)
+            .saturating_sub(scrolloff + 1)
+            .max(min_y);
+
+        let cursor_y = self.cursor_y();
+        let new_y = cursor_y.clamp(min_y, max_y);
+        self.move_cursor_vertical(new_y as isize - cursor_y as isize);
+    }
+
+    /// Scroll the view and the cursor by half a page.
+    fn scroll_half_page(&mut self, dir: isize) {
+        let amount = (self.viewport.height / 2).max(1) as isize;
+        self.viewport
+            .scroll_by(dir * amount, self.doc.rope().len_lines());
+        self.move_cursor_vertical(dir * amount);
+    }
+
+    /// The position in the document that is shown at a cell.
+    fn screen_to_pos(&self, x: usize, y: usize) -> usize {
+        // below the end of the document is the last row
+        let screen_row = self.screen_rows().take(y.saturating_add(1)).last();
+        screen_row.map_or(0, |screen_row| self.screen_row_to_pos(screen_row, x))
+    }
+
+    fn set_cursor(&mut self, pos: usize) {
+        let pos = pos.min(self.doc.rope().len_chars());
+        self.cursor_pos = pos;
+        self.cursor_ghost_pos = pos;
+    }
+
+    /// Insert pasted text as a single edit.
+    fn paste(&mut self, text: &str) {
+        // Keep it separate from anything typed in the same insert.
+        self.doc.commit_transaction(self.cursor_pos);
+
+        self.doc.begin_transaction(self.cursor_pos);
+        self.doc.insert(self.cursor_pos, text);
+        self.set_cursor(self.cursor_pos + text.chars().count());
+        self.doc.commit_transaction(self.cursor_pos);
+
+        self.doc.begin_transaction(self.cursor_pos);
+        self.inserted.push_str(text);
+    }
+
+    /// Move the cursor by a number of graphemes.
     fn move_cursor(&mut self, offset: isize) {
-        let new_pos = self
-            .cursor_pos
-            .saturating_add_signed(offset)
-            .min(self.rope.len_chars());
+        let rope = self.doc.rope();
+        let mut new_pos = self.cursor_pos;
+        for _ in 0..offset.unsigned_abs() {
+            new_pos = match offset < 0 {
+                true => grapheme::prev_boundary(rope, new_pos),
+                false => grapheme::next_boundary(rope, new_pos),
+            };
+        }
 
         self.cursor_pos = new_pos;
         self.cursor_ghost_pos = new_pos;
     }
 
+    /// Move the cursor by a number of graphemes without leaving its line,
+    /// stopping on the last grapheme unless it may go on to the end.
+    fn move_cursor_in_line(&mut self, offset: isize, to_line_end: bool) {
+        let cursor_y = self.cursor_y();
+        let rope = self.doc.rope();
+        let line_start = rope.line_to_char(cursor_y);
+        let line_end = line_start + self.line_len(cursor_y).unwrap();
+        let last = match to_line_end {
+            true => line_end,
+            false => grapheme::prev_boundary(rope, line_end).max(line_start),
+        };
+
+        let mut new_pos = self.cursor_pos;
+        for _ in 0..offset.unsigned_abs() {
+            let next = match offset < 0 {
+                true => grapheme::prev_boundary(rope, new_pos),
+                false => grapheme::next_boundary(rope, new_pos),
+            };
+            if next == new_pos || next < line_start || next > last {
+                break;
+            }
+            new_pos = next;
+        }
+
+        self.set_cursor(new_pos);
+    }
+
     fn move_cursor_vertical(&mut self, offset: isize) {
-        let current_y = self.rope.char_to_line(self.cursor_pos);
+        let current_y = self.doc.rope().char_to_line(self.cursor_pos);
         match current_y.checked_add_signed(offset) {
             None => {
                 self.cursor_pos = 0;
                 self.cursor_ghost_pos = 0;
             }
-            Some(new_y) if new_y >= self.rope.len_lines() => {
-                self.cursor_pos = self.rope.len_chars();
+            Some(new_y) if new_y >= self.doc.rope().len_lines() => {
+                self.cursor_pos = self.doc.rope().len_chars();
                 self.cursor_ghost_pos = self.cursor_pos;
             }
             Some(new_y) => {
                 let ghost_x = self.pos_to_xy(self.cursor_ghost_pos).unwrap().x;
+                self.cursor_pos = self.column_to_pos(new_y, ghost_x);
+            }
+        }
+    }
 
-                let new_line_start = self.rope.line_to_char(new_y);
-                let new_line_len = self.line_len(new_y).unwrap();
+    /// Move the cursor by a number of rows on the screen, keeping it in the
+    /// same column on the screen.
+    fn move_cursor_display(&mut self, offset: isize) {
+        if !self.config.wrap {
+            self.move_cursor_vertical(offset);
+            return;
+        }
+
+        let cursor_row = self.cursor_screen_row();
+        let x = self.row_x0(cursor_row.index) + self.pos_to_xy(self.cursor_pos).unwrap().x
+            - cursor_row.row.column;
 
-                self.cursor_pos = new_line_start.saturating_add(ghost_x.min(new_line_len));
+        let (mut line_y, mut index) = (cursor_row.line_y, cursor_row.index);
+        for _ in 0..offset.unsigned_abs() {
+            if offset < 0 {
+                if index > 0 {
+                    index -= 1;
+                } else if line_y > 0 {
+                    line_y -= 1;
+                    index = self.line_screen_rows(line_y).len() - 1;
+                }
+            } else if index + 1 < self.line_screen_rows(line_y).len() {
+                index += 1;
+            } else if line_y + 1 < self.doc.rope().len_lines() {
+                line_y += 1;
+                index = 0;
             }
         }
+
+        let screen_row = self.line_screen_rows(line_y)[index];
+        self.set_cursor(self.screen_row_to_pos(screen_row, x));
     }
 
     fn move_cursor_home(&mut self) {
-        let cursor_y = self.rope.char_to_line(self.cursor_pos);
-        self.cursor_pos = self.rope.line_to_char(cursor_y);
+        let cursor_y = self.doc.rope().char_to_line(self.cursor_pos);
+        self.cursor_pos = self.doc.rope().line_to_char(cursor_y);
         self.cursor_ghost_pos = self.cursor_pos;
     }
 
     fn move_cursor_end(&mut self) {
-        let cursor_y = self.rope.char_to_line(self.cursor_pos);
+        let cursor_y = self.doc.rope().char_to_line(self.cursor_pos);
 
-        let line_start = self.rope.line_to_char(cursor_y);
+        let line_start = self.doc.rope().line_to_char(cursor_y);
         let line_len = self.line_len(cursor_y).unwrap();
 
         self.cursor_pos = line_start.saturating_add(line_len);
@@ -190,7 +1169,999 @@ impl Pane {
     }
 }
 
+impl Pane {
+    fn enter_visual(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.selection = Some(Selection::new(self.cursor_pos, self.cursor_pos));
+        self.block_insert = None;
+    }
+
+    /// Switch to another visual mode, or leave it when it is the current one.
+    fn switch_visual(&mut self, mode: Mode) {
+        if self.mode == mode {
+            self.exit_visual();
+        } else {
+            self.mode = mode;
+        }
+    }
+
+    fn exit_visual(&mut self) {
+        if self.mode.is_visual() {
+            self.mode = Mode::Normal;
+        }
+        self.selection = None;
+    }
+
+    /// What the selection covers in the current visual mode.
+    fn selection_shape(&self) -> Option<SelectionShape> {
+        let selection = self.selection?;
+        let rope = self.doc.rope();
+        let lines = rope.char_to_line(selection.start())..=rope.char_to_line(selection.end());
+
+        let shape = match self.mode {
+            Mode::VisualLine => SelectionShape::Lines(lines),
+            Mode::VisualBlock => {
+                let anchor = self.columns_at(selection.anchor);
+                let head = self.columns_at(selection.head);
+                SelectionShape::Block {
+                    lines,
+                    columns: anchor.start.min(head.start)..anchor.end.max(head.end),
+                }
+            }
+            _ => SelectionShape::Chars(
+                selection.start()..grapheme::next_boundary(rope, selection.end()),
+            ),
+        };
+        Some(shape)
+    }
+
+    /// The display columns of the grapheme at a position, or of the cursor
+    /// when it is past the end of a line.
+    fn columns_at(&self, pos: usize) -> Range<usize> {
+        let Pos { x, y } = self.pos_to_xy(pos).unwrap();
+        let line_end = self.doc.rope().line_to_char(y) + self.line_len(y).unwrap_or(0);
+
+        let next = grapheme::next_boundary(self.doc.rope(), pos).min(line_end);
+        match next > pos {
+            true => x..self.pos_to_xy(next).unwrap().x,
+            false => x..(x + 1),
+        }
+    }
+
+    /// The part of a line that is shown in some display columns, including
+    /// graphemes that are only partly in them.
+    fn block_range(&self, line_y: usize, columns: &Range<usize>) -> Range<usize> {
+        let line_start = self.doc.rope().line_to_char(line_y);
+        let line = self.line_text(line_y);
+        let tab_width = self.config.tab_width;
+
+        let start = layout::offset_at_column(&line, columns.start, tab_width);
+        let end = layout::layout(&line, tab_width)
+            .find(|laid_out| laid_out.column >= columns.end)
+            .map_or(line.chars().count(), |laid_out| laid_out.offset);
+        (line_start + start)..(line_start + end.max(start))
+    }
+
+    /// The selected text, as it is put in a register.
+    fn shape_register(&self, shape: &SelectionShape) -> Register {
+        let rope = self.doc.rope();
+        let text = match shape {
+            SelectionShape::Chars(range) => rope.slice(range.clone()).to_string(),
+            SelectionShape::Lines(lines) => {
+                let start = rope.line_to_char(*lines.start());
+                let end = rope.line_to_char((*lines.end() + 1).min(rope.len_lines()));
+                let mut text = rope.slice(start..end).to_string();
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text
+            }
+            SelectionShape::Block { lines, columns } => lines
+                .clone()
+                .map(|line_y| rope.slice(self.block_range(line_y, columns)).to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+
+        Register {
+            text,
+            kind: shape.register_kind(),
+        }
+    }
+
+    /// Where the cursor goes after an operation on the selection.
+    fn shape_start(&self, shape: &SelectionShape) -> usize {
+        match shape {
+            SelectionShape::Chars(range) => range.start,
+            SelectionShape::Lines(lines) => self.doc.rope().line_to_char(*lines.start()),
+            SelectionShape::Block { lines, columns } => {
+                self.column_to_pos(*lines.start(), columns.start)
+            }
+        }
+    }
+
+    /// Remove the selected text, as part of the current transaction.
+    fn remove_shape(&mut self, shape: &SelectionShape) {
+        match shape {
+            SelectionShape::Chars(range) => {
+                self.doc.remove(range.clone());
+                self.set_cursor(range.start);
+            }
+            SelectionShape::Lines(lines) => self.remove_lines(lines.clone()),
+            SelectionShape::Block { lines, columns } => {
+                for line_y in lines.clone().rev() {
+                    let range = self.block_range(line_y, columns);
+                    self.doc.remove(range);
+                }
+                self.set_cursor(self.column_to_pos(*lines.start(), columns.start));
+            }
+        }
+    }
+
+    /// Delete the text and start inserting in its place, leaving the
+    /// transaction open until insert mode is left.
+    fn change_shape(&mut self, state: &mut EditorState, shape: &SelectionShape) {
+        if !self.register_writable(state) {
+            return;
+        }
+
+        state
+            .registers
+            .delete(self.register_name, self.shape_register(shape));
+
+        self.doc.begin_transaction(self.cursor_pos);
+        match shape {
+            // Keep an empty line to type on.
+            SelectionShape::Lines(lines) => {
+                let rope = self.doc.rope();
+                let start = rope.line_to_char(*lines.start());
+                let end = rope.line_to_char(*lines.end()) + self.line_len(*lines.end()).unwrap();
+                self.doc.remove(start..end);
+                self.set_cursor(start);
+            }
+            SelectionShape::Block { lines, columns } => {
+                self.remove_shape(shape);
+                self.block_insert = Some(BlockInsert {
+                    start: self.cursor_pos,
+                    lines: (*lines.start() + 1)..=*lines.end(),
+                    column: columns.start,
+                });
+            }
+            SelectionShape::Chars(_) => self.remove_shape(shape),
+        }
+        self.enter_insert();
+    }
+
+    /// Copy the text typed in place of a changed block to the block's other
+    /// lines. Lines that end before the block are left alone.
+    fn finish_block_insert(&mut self) {
+        let Some(block_insert) = self.block_insert.take() else {
+            return;
+        };
+
+        let rope = self.doc.rope();
+        if self.cursor_pos <= block_insert.start {
+            return;
+        }
+        let text = rope.slice(block_insert.start..self.cursor_pos).to_string();
+        if text.contains('\n') {
+            return;
+        }
+
+        for line_y in block_insert.lines {
+            let line = self.line_text(line_y);
+            if layout::line_width(&line, self.config.tab_width) <= block_insert.column {
+                continue;
+            }
+            let pos = self.column_to_pos(line_y, block_insert.column);
+            self.doc.insert(pos, &text);
+        }
+    }
+
+    /// Indent or dedent lines by one level.
+    fn indent_lines(&mut self, lines: RangeInclusive<usize>, dedent: bool) {
+        let indent = match self.config.expand_tab {
+            true => " ".repeat(self.config.indent_width),
+            false => "\t".to_string(),
+        };
+
+        self.doc.begin_transaction(self.cursor_pos);
+        for line_y in lines.clone() {
+            let line_start = self.doc.rope().line_to_char(line_y);
+            if dedent {
+                let len = self.dedent_len(line_y);
+                self.doc.remove(line_start..(line_start + len));
+            } else if self.line_len(line_y) != Some(0) {
+                self.doc.insert(line_start, &indent);
+            }
+        }
+
+        self.set_cursor(self.first_non_blank(*lines.start()));
+        self.doc.commit_transaction(self.cursor_pos);
+    }
+
+    /// How many chars of a line's indentation make up one level, with tabs
+    /// reaching to the next tab stop.
+    fn dedent_len(&self, line_y: usize) -> usize {
+        let tab_width = self.config.tab_width.max(1);
+        let mut column = 0;
+        let mut len = 0;
+
+        for c in self.doc.rope().line(line_y).chars() {
+            if column >= self.config.indent_width {
+                break;
+            }
+            match c {
+                ' ' => column += 1,
+                '\t' => column += tab_width - column % tab_width,
+                _ => break,
+            }
+            len += 1;
+        }
+        len
+    }
+}
+
+impl Pane {
+    fn enter_insert(&mut self) {
+        self.mode = Mode::Insert;
+        self.inserted.clear();
+    }
+
+    /// Whether the register picked with `"` can be yanked or deleted into,
+    /// showing an error if not.
+    fn register_writable(&self, state: &mut EditorState) -> bool {
+        match self.register_name {
+            Some(name) if Registers::is_read_only(name) => {
+                state.show_error(format!("Register {name} is read-only"));
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn yank_shape(&mut self, state: &mut EditorState, shape: &SelectionShape) {
+        if !self.register_writable(state) {
+            return;
+        }
+
+        state
+            .registers
+            .yank(self.register_name, self.shape_register(shape));
+
+        // Yanking lines leaves the cursor where it was, and yanking text
+        // moves it back to the start of the text, if it was after it.
+        let start = self.shape_start(shape);
+        if !matches!(shape, SelectionShape::Lines(_)) && start < self.cursor_pos {
+            self.set_cursor(start);
+        }
+    }
+
+    fn delete_shape(&mut self, state: &mut EditorState, shape: &SelectionShape) {
+        if !self.register_writable(state) {
+            return;
+        }
+
+        state
+            .registers
+            .delete(self.register_name, self.shape_register(shape));
+
+        self.doc.begin_transaction(self.cursor_pos);
+        self.remove_shape(shape);
+        self.doc.commit_transaction(self.cursor_pos);
+    }
+
+    /// The register picked with `"`, showing an error if it is empty.
+    fn register_to_put(&self, state: &mut EditorState) -> Option<Register> {
+        let register = state.registers.get(self.register_name);
+        if register.is_none() {
+            let name = self.register_name.unwrap_or('"');
+            state.show_error(format!("Nothing in register {name}"));
+        }
+        register
+    }
+
+    /// Put a register after or before the cursor, the way it was yanked.
+    fn put(&mut self, state: &mut EditorState, before: bool, count: Option<usize>) {
+        let Some(mut register) = self.register_to_put(state) else {
+            return;
+        };
+
+        let count = count.unwrap_or(1);
+        if register.text.len().saturating_mul(count) > MAX_PUT_LEN {
+            state.show_error("Too much text to put");
+            return;
+        }
+
+        // A block is repeated along its rows.
+        register.text = match register.kind {
+            RegisterKind::Blockwise => register
+                .text
+                .split('\n')
+                .map(|row| row.repeat(count))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => register.text.repeat(count),
+        };
+
+        self.doc.begin_transaction(self.cursor_pos);
+        self.put_register(&register, before);
+        self.doc.commit_transaction(self.cursor_pos);
+    }
+
+    fn put_register(&mut self, register: &Register, before: bool) {
+        let cursor_y = self.cursor_y();
+        let line_end = self.doc.rope().line_to_char(cursor_y) + self.line_len(cursor_y).unwrap();
+
+        match register.kind {
+            // The cursor ends up on the last grapheme that was put.
+            RegisterKind::Charwise => {
+                let pos = match before || self.cursor_pos >= line_end {
+                    true => self.cursor_pos,
+                    false => grapheme::next_boundary(self.doc.rope(), self.cursor_pos),
+                };
+                let end = pos + register.text.chars().count();
+
+                self.doc.insert(pos, &register.text);
+                self.set_cursor(grapheme::prev_boundary(self.doc.rope(), end).max(pos));
+            }
+
+            // The lines go above or below the cursor line, and the cursor
+            // to the first of them.
+            RegisterKind::Linewise => {
+                let rope = self.doc.rope();
+                // The empty line after a final line break isn't a line of
+                // the file, so the lines take its place.
+                let after_last_line = cursor_y > 0
+                    && cursor_y == rope.len_lines() - 1
+                    && self.line_len(cursor_y) == Some(0);
+                let line_y = if before || after_last_line {
+                    cursor_y
+                } else {
+                    cursor_y + 1
+                };
+
+                if line_y < rope.len_lines() {
+                    self.doc.insert(rope.line_to_char(line_y), &register.text);
+                } else {
+                    // after a last line that has no line break
+                    let text = register.text.strip_suffix('\n').unwrap_or(&register.text);
+                    self.doc.insert(rope.len_chars(), &format!("\n{text}"));
+                }
+                self.set_cursor(self.first_non_blank(line_y));
+            }
+
+            // Each row goes into a line from the cursor down, in the same
+            // column, with lines that are too short padded with spaces.
+            RegisterKind::Blockwise => {
+                let column = match before || self.cursor_pos >= line_end {
+                    true => self.columns_at(self.cursor_pos).start,
+                    false => self.columns_at(self.cursor_pos).end,
+                };
+
+                for (line_y, row) in (cursor_y..).zip(register.text.split('\n')) {
+                    let rope = self.doc.rope();
+                    if line_y >= rope.len_lines() {
+                        self.doc.insert(rope.len_chars(), "\n");
+                    }
+
+                    let line_end =
+                        self.doc.rope().line_to_char(line_y) + self.line_len(line_y).unwrap();
+                    let width = layout::line_width(&self.line_text(line_y), self.config.tab_width);
+                    if width < column {
+                        self.doc.insert(line_end, &" ".repeat(column - width));
+                    }
+
+                    let pos = self.column_to_pos(line_y, column);
+                    self.doc.insert(pos, row);
+                }
+                self.set_cursor(self.column_to_pos(cursor_y, column));
+            }
+        }
+    }
+
+    /// Replace the selection with a register. Unless `keep` is set, what
+    /// was replaced is then stored as if it was deleted.
+    fn put_selection(&mut self, state: &mut EditorState, keep: bool) {
+        let Some(shape) = self.selection_shape() else {
+            return;
+        };
+        let Some(register) = self.register_to_put(state) else {
+            return;
+        };
+
+        let replaced = self.shape_register(&shape);
+        self.exit_visual();
+
+        self.doc.begin_transaction(self.cursor_pos);
+        match (&shape, register.kind) {
+            // Put lines in place of lines, keeping the last line break as it
+            // was.
+            (SelectionShape::Lines(lines), _) => {
+                let rope = self.doc.rope();
+                let start = rope.line_to_char(*lines.start());
+                let end = rope.line_to_char((*lines.end() + 1).min(rope.len_lines()));
+                let ends_with_newline = end > start && rope.char(end - 1) == '\n';
+
+                let mut text = register.text.clone();
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                if !ends_with_newline {
+                    text.pop();
+                }
+
+                self.doc.remove(start..end);
+                self.doc.insert(start, &text);
+                self.set_cursor(self.first_non_blank(*lines.start()));
+            }
+
+            // Lines put in place of text within a line go on lines of their
+            // own.
+            (SelectionShape::Chars(_), RegisterKind::Linewise) => {
+                self.remove_shape(&shape);
+                let pos = self.cursor_pos;
+                self.doc.insert(pos, &format!("\n{}", register.text));
+                self.set_cursor(self.first_non_blank(self.cursor_y() + 1));
+            }
+
+            _ => {
+                self.remove_shape(&shape);
+                self.put_register(&register, true);
+            }
+        }
+        self.doc.commit_transaction(self.cursor_pos);
+
+        if !keep {
+            state.registers.delete(None, replaced);
+        }
+    }
+
+    /// The position of the first grapheme of a line that isn't a space or a
+    /// tab.
+    fn first_non_blank(&self, line_y: usize) -> usize {
+        let blank = self
+            .line_text(line_y)
+            .chars()
+            .take_while(|c| matches!(c, ' ' | '\t'))
+            .count();
+        self.doc.rope().line_to_char(line_y) + blank
+    }
+}
+
+impl Pane {
+    /// Move the cursor, `count` times for most motions.
+    fn apply_motion(&mut self, motion: Motion, count: Option<usize>) {
+        let n = count.unwrap_or(1);
+        let offset = n as isize;
+
+        match motion {
+            Motion::Left => self.move_cursor_in_line(-offset, false),
+            Motion::Right => self.move_cursor_in_line(offset, false),
+            Motion::Up => self.move_cursor_vertical(-offset),
+            Motion::Down => self.move_cursor_vertical(offset),
+            Motion::DisplayUp => self.move_cursor_display(-offset),
+            Motion::DisplayDown => self.move_cursor_display(offset),
+
+            Motion::LineStart => self.move_cursor_home(),
+            Motion::FirstNonBlank => self.set_cursor(self.first_non_blank(self.cursor_y())),
+            // to the end of the line `count - 1` lines down
+            Motion::LineEnd => {
+                self.move_cursor_vertical(offset - 1);
+                self.move_cursor_end();
+            }
+
+            Motion::WordForward
+            | Motion::WordBackward
+            | Motion::WordEnd
+            | Motion::BigWordForward
+            | Motion::BigWordBackward
+            | Motion::BigWordEnd => {
+                let rope = self.doc.rope();
+                let mut pos = self.cursor_pos;
+                for _ in 0..n {
+                    pos = match motion {
+                        Motion::WordForward => motion::word_forward(rope, pos, false),
+                        Motion::WordBackward => motion::word_backward(rope, pos, false),
+                        Motion::WordEnd => motion::word_end(rope, pos, false),
+                        Motion::BigWordForward => motion::word_forward(rope, pos, true),
+                        Motion::BigWordBackward => motion::word_backward(rope, pos, true),
+                        _ => motion::word_end(rope, pos, true),
+                    };
+                }
+                self.set_cursor(pos);
+            }
+
+            Motion::FileStart | Motion::FileEnd => {
+                let last = self.doc.rope().len_lines() - 1;
+                let line_y = match (motion, count) {
+                    (_, Some(n)) => n.saturating_sub(1).min(last),
+                    (Motion::FileStart, None) => 0,
+                    _ => last,
+                };
+                self.set_cursor(self.first_non_blank(line_y));
+            }
+        }
+    }
+
+    /// Apply an operator to what a motion or text object covers, or to
+    /// whole lines.
+    fn operate(
+        &mut self,
+        state: &mut EditorState,
+        operator: Operator,
+        target: Target,
+        count: Option<usize>,
+    ) {
+        let shape = match target {
+            Target::Motion(motion) => Some(self.motion_shape(operator, motion, count)),
+            Target::TextObject(object) => {
+                motion::text_object(self.doc.rope(), self.cursor_pos, object, count.unwrap_or(1))
+            }
+            Target::Line => {
+                let cursor_y = self.cursor_y();
+                let last = self.doc.rope().len_lines() - 1;
+                let end = cursor_y.saturating_add(count.unwrap_or(1) - 1).min(last);
+                Some(SelectionShape::Lines(cursor_y..=end))
+            }
+        };
+
+        if let Some(shape) = shape {
+            self.apply_operator(state, operator, &shape);
+        }
+    }
+
+    /// The text between the cursor and where a motion goes, without moving
+    /// the cursor.
+    fn motion_shape(
+        &mut self,
+        operator: Operator,
+        motion: Motion,
+        count: Option<usize>,
+    ) -> SelectionShape {
+        let (start, ghost) = (self.cursor_pos, self.cursor_ghost_pos);
+        let rope = self.doc.rope();
+        let start_y = rope.char_to_line(start);
+
+        // `cw` changes up to the end of the word, like `ce`, leaving the
+        // blanks after it.
+        let big = motion == Motion::BigWordForward;
+        if operator == Operator::Change
+            && (motion == Motion::WordForward || big)
+            && rope.get_char(start).is_some_and(|c| !c.is_whitespace())
+        {
+            let mut end = start;
+            for _ in 0..count.unwrap_or(1) {
+                end = motion::word_forward(rope, end, big);
+            }
+            while end > start && rope.char(end - 1).is_whitespace() {
+                end -= 1;
+            }
+            return SelectionShape::Chars(start..end);
+        }
+
+        // `l` stops on the last grapheme of a line, but `dl` there still
+        // deletes it, so an operator can go on to the end of the line.
+        if motion == Motion::Right {
+            self.move_cursor_in_line(count.unwrap_or(1) as isize, true);
+        } else {
+            self.apply_motion(motion, count);
+        }
+        let mut end = self.cursor_pos;
+        self.cursor_pos = start;
+        self.cursor_ghost_pos = ghost;
+
+        // A word motion that goes on to the next line stops at the end of
+        // the line it moved over.
+        let rope = self.doc.rope();
+        let end_y = rope.char_to_line(end);
+        if matches!(motion, Motion::WordForward | Motion::BigWordForward)
+            && end_y > start_y
+            && end <= self.first_non_blank(end_y)
+        {
+            let line_end = rope.line_to_char(end_y - 1) + self.line_len(end_y - 1).unwrap();
+            end = line_end.max(start);
+        }
+
+        match motion.kind() {
+            MotionKind::Linewise => SelectionShape::Lines(start_y.min(end_y)..=start_y.max(end_y)),
+            MotionKind::Exclusive => SelectionShape::Chars(start.min(end)..start.max(end)),
+            MotionKind::Inclusive => {
+                SelectionShape::Chars(start.min(end)..grapheme::next_boundary(rope, start.max(end)))
+            }
+        }
+    }
+
+    fn apply_operator(
+        &mut self,
+        state: &mut EditorState,
+        operator: Operator,
+        shape: &SelectionShape,
+    ) {
+        // Only a change does something with nothing, inserting there.
+        if matches!(shape, SelectionShape::Chars(range) if range.is_empty())
+            && operator != Operator::Change
+        {
+            return;
+        }
+
+        match operator {
+            Operator::Delete => self.delete_shape(state, shape),
+            Operator::Change => self.change_shape(state, shape),
+            Operator::Yank => self.yank_shape(state, shape),
+            Operator::Indent => self.indent_lines(self.shape_lines(shape), false),
+            Operator::Dedent => self.indent_lines(self.shape_lines(shape), true),
+            Operator::Lowercase => self.change_case(shape, false),
+            Operator::Uppercase => self.change_case(shape, true),
+            Operator::Reindent => self.reindent_lines(self.shape_lines(shape)),
+        }
+    }
+
+    /// Every line the text touches.
+    fn shape_lines(&self, shape: &SelectionShape) -> RangeInclusive<usize> {
+        let rope = self.doc.rope();
+        match shape {
+            SelectionShape::Chars(range) => {
+                let last = range.end.saturating_sub(1).max(range.start);
+                rope.char_to_line(range.start)..=rope.char_to_line(last)
+            }
+            SelectionShape::Lines(lines) | SelectionShape::Block { lines, .. } => lines.clone(),
+        }
+    }
+
+    fn change_case(&mut self, shape: &SelectionShape, upper: bool) {
+        let rope = self.doc.rope();
+        #[allow(clippy::single_range_in_vec_init)]
+        let ranges: Vec<Range<usize>> = match shape {
+            SelectionShape::Chars(range) => vec![range.clone()],
+            SelectionShape::Lines(lines) => {
+                let start = rope.line_to_char(*lines.start());
+                let end = rope.line_to_char(*lines.end()) + self.line_len(*lines.end()).unwrap();
+                vec![start..end]
+            }
+            SelectionShape::Block { lines, columns } => lines
+                .clone()
+                .map(|line_y| self.block_range(line_y, columns))
+                .collect(),
+        };
+
+        self.doc.begin_transaction(self.cursor_pos);
+        // from the end, as the case of some text changes its length
+        for range in ranges.into_iter().rev() {
+            let text = self.doc.rope().slice(range.clone()).to_string();
+            let changed = match upper {
+                true => text.to_uppercase(),
+                false => text.to_lowercase(),
+            };
+            if changed != text {
+                self.doc.remove(range.clone());
+                self.doc.insert(range.start, &changed);
+            }
+        }
+        self.set_cursor(self.shape_start(shape));
+        self.doc.commit_transaction(self.cursor_pos);
+    }
+
+    /// Indent lines by the brackets left open on the lines above them, a
+    /// level deeper after a line that opens one and a level shallower on a
+    /// line that closes one. Blank lines lose their indentation.
+    fn reindent_lines(&mut self, lines: RangeInclusive<usize>) {
+        let indent_width = self.config.indent_width;
+        let tab_width = self.config.tab_width.max(1);
+
+        let line_indent = |text: &str| {
+            let trimmed = text.trim_end_matches(['\r', '\n']);
+            let blank = trimmed.len() - trimmed.trim_start().len();
+            let width = layout::line_width(&trimmed[..blank], tab_width);
+            let opens = trimmed.trim_end().ends_with(['(', '[', '{']);
+            width + if opens { indent_width } else { 0 }
+        };
+
+        let mut indent = (0..*lines.start())
+            .rev()
+            .map(|line_y| self.line_text(line_y))
+            .find(|text| !text.trim().is_empty())
+            .map_or(0, |text| line_indent(&text));
+
+        self.doc.begin_transaction(self.cursor_pos);
+        for line_y in lines.clone() {
+            let text = self.line_text(line_y).into_owned();
+            let content = text.trim_end_matches(['\r', '\n']);
+            let blank = content.chars().count() - content.trim_start().chars().count();
+
+            let level = match content.trim_start() {
+                "" => 0,
+                rest if rest.starts_with([')', ']', '}']) => indent.saturating_sub(indent_width),
+                _ => indent,
+            };
+            let new_indent = match self.config.expand_tab {
+                true => " ".repeat(level),
+                false => "\t".repeat(level / tab_width) + &" ".repeat(level % tab_width),
+            };
+
+            let line_start = self.doc.rope().line_to_char(line_y);
+            if content[..content.len() - content.trim_start().len()] != new_indent {
+                self.doc.remove(line_start..(line_start + blank));
+                self.doc.insert(line_start, &new_indent);
+            }
+
+            if !content.trim().is_empty() {
+                indent = line_indent(&self.line_text(line_y));
+            }
+        }
+
+        self.set_cursor(self.first_non_blank(*lines.start()));
+        self.doc.commit_transaction(self.cursor_pos);
+    }
+}
+
 struct Pos {
     x: usize,
     y: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::SystemTime;
+
+    use super::{Mode, Pane};
+    use crate::editor::command::Command;
+    use crate::editor::event::InsertModeEvent;
+    use crate::editor::selection::SelectionShape;
+    use crate::editor::{Document, EditorState, Register, RegisterKind};
+    use crate::event::*;
+    use crate::input::Bytes;
+    use crate::ui::{ControlFlow, Widget};
+
+    fn with_text(text: &str) -> Pane {
+        let mut doc = Document::new();
+        doc.insert(0, text);
+
+        let mut pane = Pane::new(doc);
+        pane.config.tab_width = 4;
+        pane
+    }
+
+    /// Select from `anchor` to `head` in a visual mode.
+    fn select(pane: &mut Pane, mode: Mode, anchor: usize, head: usize) -> SelectionShape {
+        pane.set_cursor(anchor);
+        pane.enter_visual(mode);
+        pane.set_cursor(head);
+        pane.selection.as_mut().unwrap().head = head;
+        pane.selection_shape().unwrap()
+    }
+
+    #[test]
+    fn visual_selection() {
+        // "a", "b" and a tab to column 4, then "漢" and "字" two columns each
+        let mut pane = with_text("ab\tc\n漢字 x\nlast\n");
+
+        assert_eq!(
+            select(&mut pane, Mode::Visual, 6, 1),
+            SelectionShape::Chars(1..7)
+        );
+        assert_eq!(
+            select(&mut pane, Mode::VisualLine, 1, 6),
+            SelectionShape::Lines(0..=1)
+        );
+        let block = select(&mut pane, Mode::VisualBlock, 1, 6);
+        assert_eq!(
+            block,
+            SelectionShape::Block {
+                lines: 0..=1,
+                columns: 1..4
+            }
+        );
+
+        // graphemes partly in the columns are in the block
+        assert_eq!(pane.block_range(0, &(1..4)), 1..3);
+        assert_eq!(pane.block_range(1, &(1..4)), 5..7);
+        assert_eq!(pane.block_range(1, &(5..9)), 8..9);
+        assert_eq!(pane.block_range(2, &(5..9)), 14..14);
+
+        pane.exit_visual();
+        pane.remove_shape(&block);
+        assert_eq!(pane.doc.rope(), "ac\n x\nlast\n");
+        assert_eq!(pane.cursor_pos, 1);
+
+        // one level of indentation, with tabs reaching the next tab stop
+        let indented = with_text(" \tx\n\t  x\n  x\n      x\n");
+        let dedent: Vec<usize> = (0..4).map(|line_y| indented.dedent_len(line_y)).collect();
+        assert_eq!(dedent, [2, 1, 2, 4]);
+    }
+
+    #[test]
+    fn block_insert() {
+        let mut state = EditorState::default();
+        let mut pane = with_text("abcd\nx\nabcd\n");
+
+        let block = select(&mut pane, Mode::VisualBlock, 2, 9);
+        pane.exit_visual();
+        pane.change_shape(&mut state, &block);
+        for c in "XY".chars() {
+            pane.handle_insert_mode_event(&mut state, InsertModeEvent::InsertChar(c));
+        }
+        pane.handle_insert_mode_event(&mut state, InsertModeEvent::Escape);
+
+        // typed on the first line and copied to the others, except those
+        // that end before the block
+        assert_eq!(pane.doc.rope(), "abXYd\nx\nabXYd\n");
+
+        pane.doc.undo();
+        assert_eq!(pane.doc.rope(), "abcd\nx\nabcd\n");
+    }
+
+    #[test]
+    fn yank_cursor() {
+        let mut state = EditorState::default();
+        let mut pane = with_text("one two\nthree\n");
+
+        pane.set_cursor(5);
+        pane.yank_shape(&mut state, &SelectionShape::Lines(0..=1));
+        assert_eq!(pane.cursor_pos, 5);
+        assert_eq!(state.registers.get(None).unwrap().text, "one two\nthree\n");
+
+        pane.yank_shape(&mut state, &SelectionShape::Chars(5..7));
+        assert_eq!(pane.cursor_pos, 5);
+        pane.yank_shape(&mut state, &SelectionShape::Chars(0..3));
+        assert_eq!(pane.cursor_pos, 0);
+    }
+
+    fn send_keys(pane: &mut Pane, state: &mut EditorState, keys: &str) {
+        for c in keys.chars() {
+            let key_code = match c {
+                '\x1b' => KeyCode::Escape,
+                c => KeyCode::Char(c),
+            };
+            let event = Event {
+                bytes: Bytes::default(),
+                kind: EventKind::Key(KeyEvent::key(key_code)),
+            };
+            pane.handle_event(state, &event);
+        }
+    }
+
+    /// Type keys at the start of some text, returning the text after.
+    fn type_keys(state: &mut EditorState, text: &str, keys: &str) -> String {
+        let mut pane = with_text(text);
+        pane.set_cursor(0);
+        send_keys(&mut pane, state, keys);
+        pane.doc.rope().to_string()
+    }
+
+    #[test]
+    fn operators() {
+        let state = &mut EditorState::default();
+
+        assert_eq!(type_keys(state, "one two three four\n", "3dw"), "four\n");
+        assert_eq!(type_keys(state, "one two\nthree\n", "wdw"), "one \nthree\n");
+        assert_eq!(type_keys(state, "one two\nthree\n", "2dd"), "");
+        assert_eq!(type_keys(state, "one two\n", "gUe"), "ONE two\n");
+
+        // `cw` leaves the blanks after the word, even a word of one letter
+        assert_eq!(type_keys(state, "one two\n", "cwX\x1b"), "X two\n");
+        assert_eq!(type_keys(state, "a bc\n", "cwX\x1b"), "X bc\n");
+        assert_eq!(type_keys(state, "a bc\n", "2cwX\x1b"), "X\n");
+
+        assert_eq!(type_keys(state, "f(a, b) x\n", "llci(X\x1b"), "f(X) x\n");
+        assert_eq!(
+            type_keys(state, "a\nb\n\nc\n", ">ip"),
+            "    a\n    b\n\nc\n"
+        );
+        assert_eq!(
+            type_keys(state, "{\n(\na\n)\n  \n}\n", "=G"),
+            "{\n    (\n        a\n    )\n\n}\n"
+        );
+
+        // a block is repeated along its rows
+        state
+            .registers
+            .yank(None, Register::new("ab\ncd", RegisterKind::Blockwise));
+        assert_eq!(type_keys(state, "xy\nzw\n", "3p"), "xabababy\nzcdcdcdw\n");
+
+        // on the empty line after the last line break
+        assert_eq!(type_keys(state, "a\n", "yyjp"), "a\na\n");
+        assert_eq!(type_keys(state, "a\n", "yyjP"), "a\na\n");
+        assert_eq!(type_keys(state, "a", "yyp"), "a\na");
+
+        // `h` and `l` stay on the line, but `dl` takes the last grapheme
+        assert_eq!(type_keys(state, "ab\ncd\n", "jhdl"), "ab\nd\n");
+        assert_eq!(type_keys(state, "ab\ncd\n", "5ldl"), "a\ncd\n");
+        assert_eq!(type_keys(state, "ab\ncd\n", "l5dl"), "a\ncd\n");
+
+        // an absurd count is cut down to size
+        let mut pane = with_text("abc\n");
+        pane.set_cursor(0);
+        send_keys(&mut pane, state, "99999999999999999999l");
+        assert_eq!(pane.cursor_pos, 2);
+    }
+
+    #[test]
+    fn paste() {
+        let mut state = EditorState::default();
+        let paste = |text: &str| Event {
+            bytes: Bytes::default(),
+            kind: EventKind::Paste(text.to_owned()),
+        };
+
+        // outside insert mode it's typed in as if after `i`
+        let mut pane = with_text("ab\n");
+        pane.set_cursor(1);
+        pane.handle_event(&mut state, &paste("xy"));
+        assert_eq!(pane.mode, Mode::Insert);
+        assert_eq!(pane.doc.rope(), "axyb\n");
+
+        // even over a selection, which is dropped
+        let mut pane = with_text("ab\n");
+        select(&mut pane, Mode::Visual, 0, 1);
+        pane.handle_event(&mut state, &paste("xy"));
+        assert_eq!(pane.mode, Mode::Insert);
+        assert!(pane.selection.is_none());
+        assert_eq!(pane.doc.rope(), "axyb\n");
+
+        send_keys(&mut pane, &mut state, "\x1bu");
+        assert_eq!(pane.doc.rope(), "ab\n");
+    }
+
+    #[test]
+    fn autosave() {
+        let dir = std::env::temp_dir().join(format!("text_editor_autosave_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+        fs::write(&path, "one\n").unwrap();
+
+        let mut state = EditorState::default();
+        state.config.autosave = true;
+        let focus_lost = Event {
+            bytes: Bytes::default(),
+            kind: EventKind::FocusLost,
+        };
+
+        let mut pane = Pane::new(Document::open(&path).unwrap());
+        pane.doc.insert(0, "two\n");
+        pane.handle_event(&mut state, &focus_lost);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two\none\n");
+
+        // changed by something else, which autosaving mustn't overwrite
+        pane.doc.insert(0, "three\n");
+        fs::write(&path, "changed").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+
+        pane.handle_event(&mut state, &focus_lost);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "changed");
+        assert!(state.message().unwrap().is_error);
+        assert!(pane.doc.is_dirty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn xit() {
+        let dir = std::env::temp_dir().join(format!("text_editor_pane_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+
+        let mut state = EditorState::default();
+        let mut pane = Pane::new(Document::open(&path).unwrap());
+        let xit = Command {
+            range: None,
+            name: "xit",
+            bang: false,
+            args: "",
+        };
+
+        // nothing has changed, so there's nothing to write
+        let res = pane.execute_command(&mut state, &xit).unwrap();
+        assert_eq!(res.unwrap(), ControlFlow::Exit);
+        assert!(!path.exists());
+
+        pane.doc.insert(0, "hello\n");
+        let res = pane.execute_command(&mut state, &xit).unwrap();
+        assert_eq!(res.unwrap(), ControlFlow::Exit);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use super::clipboard::Clipboard;
+use crate::term::ClipboardKind;
+
+/// How text was yanked, which decides how it is put back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterKind {
+    Charwise,
+    /// Whole lines, each ending with a line break.
+    Linewise,
+    /// A rectangle of text, with a line break between its rows.
+    Blockwise,
+}
+
+impl RegisterKind {
+    /// The letter `:registers` shows for the kind.
+    pub fn letter(self) -> char {
+        match self {
+            Self::Charwise => 'c',
+            Self::Linewise => 'l',
+            Self::Blockwise => 'b',
+        }
+    }
+}
+
+/// Text that was yanked or deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    pub kind: RegisterKind,
+}
+
+impl Register {
+    pub fn new(text: impl Into<String>, kind: RegisterKind) -> Self {
+        Self {
+            text: text.into(),
+            kind,
+        }
+    }
+
+    /// Add text to the end of the register. Appending to or from whole
+    /// lines gives whole lines.
+    fn append(&mut self, other: Register) {
+        if other.kind == RegisterKind::Linewise && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+        self.text.push_str(&other.text);
+
+        if self.kind == RegisterKind::Linewise || other.kind == RegisterKind::Linewise {
+            self.kind = RegisterKind::Linewise;
+            if !self.text.ends_with('\n') {
+                self.text.push('\n');
+            }
+        }
+    }
+}
+
+/// The registers text is yanked into, deleted into and put from, named
+/// like vim's:
+///
+/// - `"`, the unnamed register, holds whatever was last yanked or deleted.
+/// - `0` holds the last yank, and `1` to `9` the last deletes, newest first.
+///   Deletes within a line go to `-` instead.
+/// - `a` to `z` are only written when asked for, and `A` to `Z` append to
+///   them.
+/// - `+` and `*` are the system clipboard and primary selection.
+/// - `_` is the black hole, which forgets whatever is written to it.
+/// - `.`, `:` and `/` are read-only, and hold the last inserted text, the
+///   last command line and the last search pattern.
+#[derive(Debug, Default)]
+pub struct Registers {
+    unnamed: Option<Register>,
+    yanked: Option<Register>,
+    deleted: [Option<Register>; 9],
+    small_delete: Option<Register>,
+    named: [Option<Register>; 26],
+
+    last_inserted: Option<String>,
+    last_command: Option<String>,
+    last_search: Option<String>,
+
+    clipboard: Clipboard,
+}
+
+impl Registers {
+    pub fn new(clipboard: Clipboard) -> Self {
+        Self {
+            clipboard,
+            ..Default::default()
+        }
+    }
+
+    /// Whether `name` is a register that can be picked with `"`.
+    pub fn is_valid(name: char) -> bool {
+        matches!(name, '"' | '0'..='9' | 'a'..='z' | 'A'..='Z' | '-' | '_' | '+' | '*')
+            || Self::is_read_only(name)
+    }
+
+    pub fn is_read_only(name: char) -> bool {
+        matches!(name, '.' | ':' | '/')
+    }
+
+    /// The contents of a register, or of the unnamed register when no name
+    /// is given.
+    pub fn get(&self, name: Option<char>) -> Option<Register> {
+        let charwise = |text: &Option<String>| {
+            text.as_ref()
+                .map(|text| Register::new(text.clone(), RegisterKind::Charwise))
+        };
+
+        match name.unwrap_or('"') {
+            '"' => self.unnamed.clone(),
+            '0' => self.yanked.clone(),
+            name @ '1'..='9' => self.deleted[digit(name) - 1].clone(),
+            '-' => self.small_delete.clone(),
+            '+' => self.clipboard.get(ClipboardKind::Clipboard),
+            '*' => self.clipboard.get(ClipboardKind::Primary),
+            name if name.is_ascii_alphabetic() => self.named[letter(name)].clone(),
+
+            '.' => charwise(&self.last_inserted),
+            ':' => charwise(&self.last_command),
+            '/' => charwise(&self.last_search),
+            _ => None,
+        }
+    }
+
+    /// Store yanked text, in `0` unless a register is named.
+    pub fn yank(&mut self, name: Option<char>, register: Register) {
+        match name {
+            None | Some('"') => {
+                self.yanked = Some(register.clone());
+                self.unnamed = Some(register);
+            }
+            Some(name) => self.set(name, register),
+        }
+    }
+
+    /// Store deleted text, shifting the delete history unless a register
+    /// is named or the text is within a line.
+    pub fn delete(&mut self, name: Option<char>, register: Register) {
+        match name {
+            None | Some('"')
+                if register.kind == RegisterKind::Charwise && !register.text.contains('\n') =>
+            {
+                self.small_delete = Some(register.clone());
+                self.unnamed = Some(register);
+            }
+            None | Some('"') => {
+                self.deleted.rotate_right(1);
+                self.deleted[0] = Some(register.clone());
+                self.unnamed = Some(register);
+            }
+            Some(name) => self.set(name, register),
+        }
+    }
+
+    /// Write to a named register, which the unnamed register then also
+    /// holds.
+    fn set(&mut self, name: char, register: Register) {
+        let register = match name {
+            '_' => return,
+            '0' => self.yanked.insert(register),
+            name @ '1'..='9' => self.deleted[digit(name) - 1].insert(register),
+            '-' => self.small_delete.insert(register),
+            '+' | '*' => {
+                let kind = match name {
+                    '+' => ClipboardKind::Clipboard,
+                    _ => ClipboardKind::Primary,
+                };
+                self.clipboard.set(kind, register.clone());
+                self.unnamed = Some(register);
+                return;
+            }
+            name if name.is_ascii_lowercase() => self.named[letter(name)].insert(register),
+            name if name.is_ascii_uppercase() => match &mut self.named[letter(name)] {
+                Some(named) => {
+                    named.append(register);
+                    named
+                }
+                named @ None => named.insert(register),
+            },
+            _ => return,
+        };
+
+        self.unnamed = Some(register.clone());
+    }
+
+    pub fn set_last_inserted(&mut self, text: impl Into<String>) {
+        self.last_inserted = Some(text.into());
+    }
+
+    pub fn set_last_command(&mut self, text: impl Into<String>) {
+        self.last_command = Some(text.into());
+    }
+
+    pub fn set_last_search(&mut self, text: impl Into<String>) {
+        self.last_search = Some(text.into());
+    }
+
+    /// Every register that holds something, in the order `:registers`
+    /// lists them. The clipboard registers hold what was last copied from
+    /// here, as asking the system clipboard can be slow.
+    pub fn iter(&self) -> impl Iterator<Item = (char, Register)> + '_ {
+        ['"']
+            .into_iter()
+            .chain('0'..='9')
+            .chain('a'..='z')
+            .chain(['-', '*', '+', '.', ':', '/'])
+            .filter_map(|name| {
+                let register = match name {
+                    '+' => self.clipboard.internal(ClipboardKind::Clipboard).cloned(),
+                    '*' => self.clipboard.internal(ClipboardKind::Primary).cloned(),
+                    name => self.get(Some(name)),
+                };
+                Some((name, register?))
+            })
+    }
+}
+
+fn digit(name: char) -> usize {
+    name as usize - '0' as usize
+}
+
+fn letter(name: char) -> usize {
+    name.to_ascii_lowercase() as usize - 'a' as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Register, RegisterKind, Registers};
+
+    #[test]
+    fn registers() {
+        let chars = |text: &str| Register::new(text, RegisterKind::Charwise);
+        let lines = |text: &str| Register::new(text, RegisterKind::Linewise);
+
+        let mut registers = Registers::default();
+        registers.yank(None, chars("yanked"));
+        registers.delete(None, lines("one\n"));
+        registers.delete(None, lines("two\n"));
+        registers.delete(None, chars("word"));
+
+        assert_eq!(registers.get(None), Some(chars("word")));
+        assert_eq!(registers.get(Some('0')), Some(chars("yanked")));
+        assert_eq!(registers.get(Some('1')), Some(lines("two\n")));
+        assert_eq!(registers.get(Some('2')), Some(lines("one\n")));
+        assert_eq!(registers.get(Some('-')), Some(chars("word")));
+
+        registers.yank(Some('a'), chars("x"));
+        registers.yank(Some('A'), lines("y\n"));
+        assert_eq!(registers.get(Some('a')), Some(lines("x\ny\n")));
+        assert_eq!(registers.get(None), Some(lines("x\ny\n")));
+        assert_eq!(registers.get(Some('0')), Some(chars("yanked")));
+
+        registers.delete(Some('_'), chars("gone"));
+        assert_eq!(registers.get(None), Some(lines("x\ny\n")));
+
+        registers.set_last_command("w");
+        let names: String = registers.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, "\"012a-:");
+    }
+}
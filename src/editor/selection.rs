@@ -0,0 +1,68 @@
+use std::ops::{Range, RangeInclusive};
+
+use super::register::RegisterKind;
+
+/// A range of text between the anchor, where it was started, and the
+/// head, which moves with the cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+}
+
+impl Selection {
+    pub fn new(anchor: usize, head: usize) -> Self {
+        Self { anchor, head }
+    }
+
+    /// The first selected position, whichever end it is at.
+    pub fn start(&self) -> usize {
+        self.anchor.min(self.head)
+    }
+
+    /// The last selected position, which is selected along with the rest of
+    /// its grapheme.
+    pub fn end(&self) -> usize {
+        self.anchor.max(self.head)
+    }
+}
+
+/// What a selection covers, which depends on the visual mode it was made
+/// in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectionShape {
+    Chars(Range<usize>),
+    Lines(RangeInclusive<usize>),
+    /// The lines, and the display columns within each of them.
+    Block {
+        lines: RangeInclusive<usize>,
+        columns: Range<usize>,
+    },
+}
+
+impl SelectionShape {
+    /// Whether a grapheme is selected, given its position, line and the
+    /// columns it is shown in.
+    pub fn contains(&self, pos: usize, line_y: usize, columns: Range<usize>) -> bool {
+        match self {
+            Self::Chars(range) => range.contains(&pos),
+            Self::Lines(lines) => lines.contains(&line_y),
+            Self::Block {
+                lines,
+                columns: block_columns,
+            } => {
+                lines.contains(&line_y)
+                    && columns.start < block_columns.end
+                    && block_columns.start < columns.end
+            }
+        }
+    }
+
+    pub fn register_kind(&self) -> RegisterKind {
+        match self {
+            Self::Chars(_) => RegisterKind::Charwise,
+            Self::Lines(_) => RegisterKind::Linewise,
+            Self::Block { .. } => RegisterKind::Blockwise,
+        }
+    }
+}
@@ -1,51 +1,62 @@
-use ropey::Rope;
-
+use super::document::Document;
 use super::event::InsertModeEvent;
 use super::EditorState;
 use crate::buffer::Buffer;
 use crate::event::*;
+use crate::grapheme;
 use crate::ui::*;
 
 #[derive(Default)]
 pub struct TextField {
-    rope: Rope,
+    doc: Document,
     cursor_pos: usize,
 }
 
 impl Widget<EditorState> for TextField {
     fn handle_event(&mut self, state: &mut EditorState, event: &Event) -> Option<ControlFlow> {
+        if let EventKind::Mouse {
+            kind: MouseKind::Press,
+            button: Some(MouseButton::Left),
+            x,
+            ..
+        } = event.kind
+        {
+            self.cursor_pos = grapheme::offset_at_column(&self.value(), x);
+            return Some(ControlFlow::Continue);
+        }
+
+        if let EventKind::Paste(text) = &event.kind {
+            self.doc.insert(self.cursor_pos, text);
+            self.cursor_pos += text.chars().count();
+            return Some(ControlFlow::Continue);
+        }
+
         state.key_maps.insert_mode(event).and_then(|event| {
             match event {
                 InsertModeEvent::InsertChar(c) => {
-                    self.rope.insert_char(self.cursor_pos, c);
-                    self.move_cursor(1);
-                }
-
-                InsertModeEvent::InsertString(s) => {
-                    self.rope.insert(self.cursor_pos, s);
-                    // conversion could *technically* overflow
-                    self.move_cursor(s.chars().count() as isize);
+                    self.doc.insert_char(self.cursor_pos, c);
+                    self.cursor_pos += 1;
                 }
 
                 InsertModeEvent::Delete => {
-                    let _ = self
-                        .rope
-                        .try_remove(self.cursor_pos..(self.cursor_pos.saturating_add(1)));
+                    let end = grapheme::next_boundary(self.doc.rope(), self.cursor_pos);
+                    self.doc.remove(self.cursor_pos..end);
                 }
 
                 InsertModeEvent::Backspace => {
-                    let new_pos = self.cursor_pos.saturating_sub(1);
-                    let _ = self.rope.try_remove(new_pos..self.cursor_pos);
-                    self.move_cursor(-1);
+                    let new_pos = grapheme::prev_boundary(self.doc.rope(), self.cursor_pos);
+                    self.doc.remove(new_pos..self.cursor_pos);
+                    self.cursor_pos = new_pos;
                 }
 
                 InsertModeEvent::MoveLeft => self.move_cursor(-1),
                 InsertModeEvent::MoveRight => self.move_cursor(1),
 
                 InsertModeEvent::MoveHome => self.cursor_pos = 0,
-                InsertModeEvent::MoveEnd => self.cursor_pos = self.rope.len_chars(),
+                InsertModeEvent::MoveEnd => self.cursor_pos = self.doc.rope().len_chars(),
 
                 InsertModeEvent::MoveUp | InsertModeEvent::MoveDown => return None,
+                InsertModeEvent::PageUp | InsertModeEvent::PageDown => return None,
                 InsertModeEvent::Escape => return None,
             }
 
@@ -53,40 +64,43 @@ impl Widget<EditorState> for TextField {
         })
     }
 
-    fn update(&mut self, _state: &mut EditorState) -> ControlFlow {
-        ControlFlow::Continue
+    fn update(&mut self, _state: &mut EditorState) -> Update {
+        Update::default()
     }
 
-    fn render(&mut self, buf: &mut Buffer) {
+    fn render(&mut self, _state: &EditorState, buf: &mut Buffer) {
         if buf.height() == 0 || buf.width() == 0 {
             return;
         }
 
-        for (x, c) in self.rope.chars().enumerate().take(buf.width()) {
-            buf[[x, 0]].c = c;
-        }
+        let value = self.value();
+        buf.set_str(0, 0, &value);
 
-        if self.cursor_pos < buf.width() {
-            buf.set_cursor(Some((self.cursor_pos, 0)));
+        let cursor_x = grapheme::slice_width(self.doc.rope().slice(..self.cursor_pos));
+        if cursor_x < buf.width() {
+            buf.set_cursor(Some((cursor_x, 0)));
         }
     }
 }
 
 impl TextField {
     pub fn value(&self) -> String {
-        self.rope.to_string()
+        self.doc.rope().to_string()
     }
 
     pub fn clear(&mut self) {
-        self.rope.remove(..);
+        self.doc = Document::new();
         self.cursor_pos = 0;
     }
 
+    /// Move the cursor by a number of graphemes.
     fn move_cursor(&mut self, offset: isize) {
-        let new_pos = self
-            .cursor_pos
-            .saturating_add_signed(offset)
-            .min(self.rope.len_chars());
-        self.cursor_pos = new_pos;
+        let rope = self.doc.rope();
+        for _ in 0..offset.unsigned_abs() {
+            self.cursor_pos = match offset < 0 {
+                true => grapheme::prev_boundary(rope, self.cursor_pos),
+                false => grapheme::next_boundary(rope, self.cursor_pos),
+            };
+        }
     }
 }
@@ -0,0 +1,183 @@
+/// The part of a document that is visible in a pane.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    /// The first visible line.
+    pub top: usize,
+    /// The first visible row of the top line, when lines are wrapped.
+    pub top_row: usize,
+    /// The first visible column.
+    pub left: usize,
+
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Viewport {
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Scroll the least amount needed for the cursor to be visible with
+    /// `scrolloff` lines of context above and below it.
+    pub fn scroll_to(&mut self, x: usize, y: usize, scrolloff: usize) {
+        if self.height > 0 {
+            let scrolloff = self.clamp_scrolloff(scrolloff);
+            self.top_row = 0;
+
+            if y < self.top.saturating_add(scrolloff) {
+                self.top = y.saturating_sub(scrolloff);
+            } else if y.saturating_add(scrolloff) >= self.top.saturating_add(self.height) {
+                self.top = (y + scrolloff + 1).saturating_sub(self.height);
+            }
+        }
+
+        if self.width > 0 {
+            if x < self.left {
+                self.left = x;
+            } else if x >= self.left.saturating_add(self.width) {
+                self.left = x + 1 - self.width;
+            }
+        }
+    }
+
+    /// Like [`Viewport::scroll_to`], but in screen rows for when lines are
+    /// wrapped: `row` is the row of line `y` to show, and `rows` gives how
+    /// many rows a line takes up.
+    pub fn scroll_to_row(
+        &mut self,
+        y: usize,
+        row: usize,
+        scrolloff: usize,
+        rows: impl Fn(usize) -> usize,
+    ) {
+        self.left = 0;
+
+        if self.height == 0 {
+            return;
+        }
+
+        let scrolloff = self.clamp_scrolloff(scrolloff);
+
+        // How far below the top the row is, counting no further than the
+        // bottom.
+        let distance = ((y, row) >= (self.top, self.top_row)).then(|| {
+            let mut distance = 0;
+            let mut top_row = self.top_row;
+            for line in self.top..y {
+                distance += rows(line).saturating_sub(top_row);
+                top_row = 0;
+                if distance >= self.height {
+                    return distance;
+                }
+            }
+            distance + row - top_row
+        });
+
+        match distance {
+            Some(distance) if distance < scrolloff => self.top_above(y, row, scrolloff, rows),
+            Some(distance) if distance + scrolloff < self.height => {}
+            Some(_) => self.top_above(y, row, self.height - scrolloff - 1, rows),
+            None => self.top_above(y, row, scrolloff, rows),
+        }
+    }
+
+    /// Scroll so that the top is `count` rows above row `row` of line `y`.
+    fn top_above(
+        &mut self,
+        mut y: usize,
+        mut row: usize,
+        mut count: usize,
+        rows: impl Fn(usize) -> usize,
+    ) {
+        while count > row && y > 0 {
+            count -= row + 1;
+            y -= 1;
+            row = rows(y).saturating_sub(1);
+        }
+
+        self.top = y;
+        self.top_row = row.saturating_sub(count);
+    }
+
+    /// Scroll by a number of lines, without going past the last line.
+    pub fn scroll_by(&mut self, lines: isize, line_count: usize) {
+        self.top_row = 0;
+        self.top = self
+            .top
+            .saturating_add_signed(lines)
+            .min(line_count.saturating_sub(1));
+    }
+
+    /// Scroll so that line `y` is in the middle of the viewport.
+    pub fn center_on(&mut self, y: usize) {
+        self.top_row = 0;
+        self.top = y.saturating_sub(self.height / 2);
+    }
+
+    /// Scroll so that line `y` is at the top of the viewport.
+    pub fn top_on(&mut self, y: usize, scrolloff: usize) {
+        self.top_row = 0;
+        self.top = y.saturating_sub(self.clamp_scrolloff(scrolloff));
+    }
+
+    /// Scroll so that line `y` is at the bottom of the viewport.
+    pub fn bottom_on(&mut self, y: usize, scrolloff: usize) {
+        self.top_row = 0;
+        self.top = (y + self.clamp_scrolloff(scrolloff) + 1).saturating_sub(self.height);
+    }
+
+    /// The scroll offset can't be more than half the viewport, otherwise
+    /// the cursor would have nowhere to go.
+    fn clamp_scrolloff(&self, scrolloff: usize) -> usize {
+        scrolloff.min(self.height.saturating_sub(1) / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Viewport;
+
+    #[test]
+    fn follow_cursor() {
+        let mut viewport = Viewport::default();
+        viewport.resize(10, 10);
+
+        viewport.scroll_to(0, 7, 3);
+        assert_eq!(viewport.top, 1);
+
+        viewport.scroll_to(0, 20, 3);
+        assert_eq!(viewport.top, 14);
+
+        viewport.scroll_to(0, 15, 3);
+        assert_eq!(viewport.top, 12);
+
+        // scrolloff larger than the viewport keeps the cursor centered
+        viewport.scroll_to(0, 30, 100);
+        assert_eq!(viewport.top, 25);
+
+        viewport.scroll_to(25, 30, 0);
+        assert_eq!(viewport.left, 16);
+        viewport.scroll_to(3, 30, 0);
+        assert_eq!(viewport.left, 3);
+
+        viewport.bottom_on(30, 0);
+        assert_eq!(viewport.top, 21);
+        viewport.center_on(30);
+        assert_eq!(viewport.top, 25);
+
+        // every line wraps into 3 rows
+        viewport.scroll_to_row(30, 0, 3, |_| 3);
+        assert_eq!((viewport.top, viewport.top_row), (28, 0));
+        viewport.scroll_to_row(31, 2, 3, |_| 3);
+        assert_eq!((viewport.top, viewport.top_row), (29, 2));
+        viewport.scroll_to_row(32, 0, 3, |_| 3);
+        assert_eq!((viewport.top, viewport.top_row), (30, 0));
+        viewport.scroll_to_row(31, 0, 3, |_| 3);
+        assert_eq!((viewport.top, viewport.top_row), (30, 0));
+        viewport.scroll_to_row(1, 1, 3, |_| 3);
+        assert_eq!((viewport.top, viewport.top_row), (0, 1));
+        viewport.scroll_to_row(0, 1, 3, |_| 3);
+        assert_eq!((viewport.top, viewport.top_row), (0, 0));
+    }
+}
@@ -41,6 +41,17 @@ impl VSplit {
 
 impl Widget<EditorState> for VSplit {
     fn handle_event(&mut self, state: &mut EditorState, event: &Event) -> Option<ControlFlow> {
+        if let EventKind::Mouse { kind, y, .. } = event.kind {
+            return self.handle_mouse_event(state, event, kind, y);
+        }
+
+        // Both splits are in the same terminal.
+        if let EventKind::FocusGained | EventKind::FocusLost = event.kind {
+            let top = self.top.handle_event(state, event);
+            let bottom = self.bottom.handle_event(state, event);
+            return top.or(bottom);
+        }
+
         match state.key_maps.vsplit(event) {
             Some(event) => match event {
                 VSplitEvent::FocusUp if self.focus == Focus::Bottom => {
@@ -61,14 +72,15 @@ impl Widget<EditorState> for VSplit {
         }
     }
 
-    fn update(&mut self, state: &mut EditorState) -> ControlFlow {
-        if let ControlFlow::Exit = self.top.update(state) {
-            return ControlFlow::Exit;
+    fn update(&mut self, state: &mut EditorState) -> Update {
+        let top = self.top.update(state);
+        if let ControlFlow::Exit = top.control_flow {
+            return top;
         }
-        self.bottom.update(state)
+        top.merge(self.bottom.update(state))
     }
 
-    fn render(&mut self, buf: &mut Buffer) {
+    fn render(&mut self, state: &EditorState, buf: &mut Buffer) {
         let (top_size, bottom_size) = match (self.top_constraint, self.bottom_constraint) {
             (None, None) | (Some(_), Some(_)) => {
                 let size = buf.height() / 2;
@@ -88,8 +100,8 @@ impl Widget<EditorState> for VSplit {
         self.bottom_buffer
             .resize_and_clear(buf.width(), bottom_size);
 
-        self.top.render(&mut self.top_buffer);
-        self.bottom.render(&mut self.bottom_buffer);
+        self.top.render(state, &mut self.top_buffer);
+        self.bottom.render(state, &mut self.bottom_buffer);
 
         buf.blit(0, 0, &self.top_buffer, self.focus == Focus::Top);
         buf.blit(
@@ -101,6 +113,40 @@ impl Widget<EditorState> for VSplit {
     }
 }
 
+impl VSplit {
+    /// Send a mouse event to the split under it, focusing it if it was
+    /// clicked. Drags and releases go to the focused split.
+    fn handle_mouse_event(
+        &mut self,
+        state: &mut EditorState,
+        event: &Event,
+        kind: MouseKind,
+        y: usize,
+    ) -> Option<ControlFlow> {
+        let top_height = self.top_buffer.height();
+
+        let target = match kind {
+            MouseKind::Drag | MouseKind::Release => self.focus,
+            _ if y < top_height => Focus::Top,
+            _ => Focus::Bottom,
+        };
+
+        let focused = kind == MouseKind::Press && self.focus != target;
+        if kind == MouseKind::Press {
+            self.focus = target;
+        }
+
+        let control_flow = match target {
+            Focus::Top => self.top.handle_event(state, event),
+            Focus::Bottom => self
+                .bottom
+                .handle_event(state, &event.relative_to(0, top_height)),
+        };
+
+        control_flow.or(focused.then_some(ControlFlow::Continue))
+    }
+}
+
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 enum Focus {
     #[default]
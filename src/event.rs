@@ -1,9 +1,10 @@
 use std::io;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use bitflags::bitflags;
+use libc::c_int;
 
-use crate::input::{Bytes, PollingStdin};
+use crate::input::{Bytes, Input, PollingInput};
 
 #[derive(Debug, Clone)]
 pub struct Event {
@@ -11,19 +12,63 @@ pub struct Event {
     pub kind: EventKind,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum EventKind {
     Key(KeyEvent),
-    String(String),
+
+    /// Text pasted while bracketed paste was enabled.
+    Paste(String),
+
+    /// A mouse event, at a cell relative to the widget receiving it.
+    Mouse {
+        kind: MouseKind,
+        button: Option<MouseButton>,
+        x: usize,
+        y: usize,
+        modifiers: Modifiers,
+    },
+
+    Resize {
+        width: usize,
+        height: usize,
+    },
+
+    /// The process was asked to exit by `SIGTERM` or `SIGHUP`.
+    Terminate,
+
+    /// The process was continued after being stopped.
+    Resume,
+
+    FocusGained,
+    FocusLost,
+
+    /// The terminal replied that it supports the kitty keyboard protocol.
+    KeyboardProtocol,
+
     Unknown,
 }
 
+impl Event {
+    /// Make the position of a mouse event relative to a widget at `(x, y)`,
+    /// clamping it to the widget's top left.
+    pub fn relative_to(&self, x: usize, y: usize) -> Event {
+        let mut event = self.clone();
+        if let EventKind::Mouse {
+            x: mouse_x,
+            y: mouse_y,
+            ..
+        } = &mut event.kind
+        {
+            *mouse_x = mouse_x.saturating_sub(x);
+            *mouse_y = mouse_y.saturating_sub(y);
+        }
+        event
+    }
+}
+
 impl EventKind {
     fn just_key(key_code: KeyCode) -> Self {
-        Self::Key(KeyEvent {
-            key_code,
-            modifiers: Modifiers::empty(),
-        })
+        Self::Key(KeyEvent::key(key_code))
     }
 }
 
@@ -31,15 +76,30 @@ impl EventKind {
 pub struct KeyEvent {
     pub key_code: KeyCode,
     pub modifiers: Modifiers,
+    pub kind: KeyEventKind,
 }
 
 impl KeyEvent {
-    pub fn key(key_code: KeyCode) -> Self {
+    pub fn new(key_code: KeyCode, modifiers: Modifiers) -> Self {
         Self {
             key_code,
-            modifiers: Modifiers::empty(),
+            modifiers,
+            kind: KeyEventKind::Press,
         }
     }
+
+    pub fn key(key_code: KeyCode) -> Self {
+        Self::new(key_code, Modifiers::empty())
+    }
+}
+
+/// Repeats and releases are only reported by the kitty keyboard protocol.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyEventKind {
+    #[default]
+    Press,
+    Repeat,
+    Release,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -48,6 +108,8 @@ pub enum KeyCode {
     Fn(u8),
 
     Tab,
+    /// Shift-Tab. Shift is implied, so isn't included in the modifiers.
+    BackTab,
     Newline,
     Return,
 
@@ -69,6 +131,26 @@ pub enum KeyCode {
     PageDown,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseKind {
+    Press,
+    Release,
+    /// Moved with a button held.
+    Drag,
+
+    ScrollUp,
+    ScrollDown,
+    ScrollLeft,
+    ScrollRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct Modifiers: u8 {
@@ -79,183 +161,654 @@ bitflags! {
     }
 }
 
-#[derive(Default)]
+/// Marks the start of pasted text, in bracketed paste mode.
+const PASTE_START: &[u8] = b"\x1b[200~";
+/// Marks the end of pasted text.
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// How long to wait for the rest of an escape sequence by default.
+pub const DEFAULT_ESCAPE_TIMEOUT: Duration = Duration::from_millis(50);
+
 pub struct EventReader {
-    stdin: PollingStdin,
+    input: PollingInput,
+    decoder: Decoder,
+    escape_timeout: Duration,
 }
 
 impl EventReader {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            input: PollingInput::new()?,
+            decoder: Decoder::default(),
+            escape_timeout: DEFAULT_ESCAPE_TIMEOUT,
+        })
     }
 
-    pub fn read_with_deadline(&self, deadline: Instant) -> io::Result<Option<Event>> {
-        let Some(bytes) = self.stdin.read_with_deadline(deadline)? else {
-            return Ok(None);
-        };
-        let kind = parse_event(bytes.as_slice()).unwrap_or(EventKind::Unknown);
-        Ok(Some(Event { kind, bytes }))
+    /// Set how long to wait for the rest of an escape sequence before
+    /// giving up on it, so that a lone escape is reported as the escape
+    /// key rather than an alt prefix.
+    pub fn set_escape_timeout(&mut self, timeout: Duration) {
+        self.escape_timeout = timeout;
     }
-}
 
-fn parse_event(bytes: &[u8]) -> Option<EventKind> {
-    // print!("bytes: {bytes:?}\r\n");
+    /// Wait for an event until the deadline, or indefinitely if there is none.
+    pub fn read_with_deadline(&mut self, deadline: Option<Instant>) -> io::Result<Option<Event>> {
+        loop {
+            if let Some(event) = self.decoder.next() {
+                return Ok(Some(event));
+            }
 
-    let (&first, rest) = bytes.split_first()?;
+            let timeout = self
+                .decoder
+                .incomplete_since()
+                .map(|since| since + self.escape_timeout);
 
-    let event = match first {
-        b'\x1b' => {
-            match rest {
-                b"" | b"\x1b" => EventKind::just_key(KeyCode::Escape),
+            let wait_until = match (deadline, timeout) {
+                (Some(deadline), Some(timeout)) => Some(deadline.min(timeout)),
+                (deadline, timeout) => deadline.or(timeout),
+            };
 
-                b"[" => EventKind::Key(KeyEvent {
-                    key_code: KeyCode::Char('['),
-                    modifiers: Modifiers::ALT,
-                }),
+            match self.input.read_with_deadline(wait_until)? {
+                Some(Input::Bytes(bytes)) => self.decoder.feed(bytes.as_slice()),
+                Some(Input::Signal(signal)) => return signal_event(signal).map(Some),
+                None => {
+                    let now = Instant::now();
 
-                // vt sequence
-                [b'[', rest @ .., b'~'] => {
-                    let (key_code, modifiers) =
-                        if let Some(idx) = rest.iter().position(|&byte| byte == b';') {
-                            let (key_code, modifiers) = rest.split_at(idx);
-                            Some((key_code, parse_modifiers(modifiers)?))
-                        } else {
-                            Some((rest, Modifiers::empty()))
-                        }?;
-
-                    let key_code = match key_code {
-                        b"1" => KeyCode::Home,
-                        b"2" => KeyCode::Insert,
-                        b"3" => KeyCode::Delete,
-                        b"4" => KeyCode::End,
-                        b"5" => KeyCode::PageUp,
-                        b"6" => KeyCode::PageDown,
-                        b"7" => KeyCode::Home,
-                        b"8" => KeyCode::End,
-
-                        b"11" => KeyCode::Fn(1),
-                        b"12" => KeyCode::Fn(2),
-                        b"13" => KeyCode::Fn(3),
-                        b"14" => KeyCode::Fn(4),
-                        b"15" => KeyCode::Fn(5),
-
-                        // no this isn't a typo, `16` is skipped
-                        b"17" => KeyCode::Fn(6),
-                        b"18" => KeyCode::Fn(7),
-                        b"19" => KeyCode::Fn(8),
-                        b"20" => KeyCode::Fn(9),
-                        b"21" => KeyCode::Fn(10),
-
-                        // who needs more than 10 function keys?
-                        // let's leave it at that.
-                        _ => return None,
-                    };
-
-                    EventKind::Key(KeyEvent {
-                        key_code,
-                        modifiers,
-                    })
-                }
+                    if timeout.is_some_and(|timeout| timeout <= now) {
+                        return Ok(self.decoder.flush());
+                    }
 
-                // xterm sequence
-                [b'[', modifiers @ .., key_code] => {
-                    let key_code = match key_code {
-                        b'A' => KeyCode::Up,
-                        b'B' => KeyCode::Down,
-                        b'C' => KeyCode::Right,
-                        b'D' => KeyCode::Left,
-
-                        b'F' => KeyCode::End,
-                        b'H' => KeyCode::Home,
-
-                        b'P' => KeyCode::Fn(1),
-                        b'Q' => KeyCode::Fn(2),
-                        b'R' => KeyCode::Fn(3),
-                        b'S' => KeyCode::Fn(4),
-
-                        _ => return None,
-                    };
-
-                    let modifiers =
-                        if let Some(index) = modifiers.iter().position(|&byte| byte == b';') {
-                            modifiers.split_at(index.saturating_add(1)).1
-                        } else {
-                            modifiers
-                        };
-
-                    let modifiers = if modifiers.is_empty() {
-                        Modifiers::empty()
-                    } else {
-                        parse_modifiers(modifiers)?
-                    };
-
-                    EventKind::Key(KeyEvent {
-                        key_code,
-                        modifiers,
-                    })
+                    if deadline.is_some_and(|deadline| deadline <= now) {
+                        return Ok(None);
+                    }
                 }
+            }
+        }
+    }
+}
 
-                [c] => {
-                    let mut key_event = decode_byte(*c);
-                    key_event.modifiers |= Modifiers::ALT;
-                    EventKind::Key(key_event)
-                }
+fn signal_event(signal: c_int) -> io::Result<Event> {
+    let kind = match signal {
+        libc::SIGWINCH => {
+            let (width, height) = crate::term::size()?;
+            EventKind::Resize { width, height }
+        }
+
+        libc::SIGTERM | libc::SIGHUP => EventKind::Terminate,
+        libc::SIGCONT => EventKind::Resume,
+
+        _ => EventKind::Unknown,
+    };
+
+    Ok(Event {
+        kind,
+        bytes: Bytes::default(),
+    })
+}
+
+/// Splits a stream of input into events.
+///
+/// A sequence can be split across reads, so an incomplete one is kept
+/// until the rest of it arrives, or until [`Decoder::flush`] gives up on it.
+#[derive(Debug)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    last_input: Instant,
+    /// How much of a paste that is still arriving has been searched for
+    /// its end.
+    paste_scanned: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self {
+            buf: vec![],
+            last_input: Instant::now(),
+            paste_scanned: 0,
+        }
+    }
+}
+
+impl Decoder {
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+        self.last_input = Instant::now();
+    }
+
+    /// When the input that is still waiting to be decoded arrived, if any.
+    ///
+    /// This includes a paste, which may never see its end if the terminal
+    /// was interrupted while sending it.
+    pub fn incomplete_since(&self) -> Option<Instant> {
+        (!self.buf.is_empty()).then_some(self.last_input)
+    }
+
+    /// Stop waiting for the rest of an incomplete sequence, and decode
+    /// what there is of it.
+    ///
+    /// An escape followed by part of a sequence is taken to be an alt
+    /// prefix, and an escape on its own is the escape key. A paste ends
+    /// with what there is of it.
+    pub fn flush(&mut self) -> Option<Event> {
+        let (kind, len) = match self.buf.as_slice() {
+            [] => return None,
+
+            bytes if bytes.starts_with(PASTE_START) => (
+                EventKind::Paste(paste_text(&bytes[PASTE_START.len()..])),
+                bytes.len(),
+            ),
+
+            [b'\x1b', rest @ ..] => match parse_char(rest) {
+                Parsed::Event(kind, len) => (with_alt(kind), len + 1),
+                Parsed::Incomplete => (EventKind::just_key(KeyCode::Escape), 1),
+            },
 
-                _ => return None,
+            // part of a character
+            bytes => (EventKind::Unknown, bytes.len()),
+        };
+
+        Some(self.take(kind, len))
+    }
+
+    fn take(&mut self, kind: EventKind, len: usize) -> Event {
+        let bytes = Bytes::from(&self.buf[..len]);
+        self.buf.drain(..len);
+        self.paste_scanned = 0;
+        Event { kind, bytes }
+    }
+}
+
+impl Iterator for Decoder {
+    type Item = Event;
+
+    /// Decode the next complete event.
+    fn next(&mut self) -> Option<Event> {
+        let parsed = match self.buf.strip_prefix(PASTE_START) {
+            // A long paste arrives over many reads, so only search what
+            // arrived since the last search, and the part of an end that
+            // may have been split across them.
+            Some(text) => {
+                let from = self.paste_scanned.saturating_sub(PASTE_END.len() - 1);
+                self.paste_scanned = text.len();
+                parse_paste(text, from).map(|kind| kind, PASTE_START.len())
             }
+            None => parse_event(&self.buf),
+        };
+
+        match parsed {
+            Parsed::Event(kind, len) => Some(self.take(kind, len)),
+            Parsed::Incomplete => None,
+        }
+    }
+}
+
+/// The result of parsing the start of the input.
+enum Parsed {
+    /// An event, and how many bytes it took up.
+    Event(EventKind, usize),
+
+    /// More input is needed to tell what the event is.
+    Incomplete,
+}
+
+impl Parsed {
+    fn map(self, f: impl FnOnce(EventKind) -> EventKind, offset: usize) -> Self {
+        match self {
+            Self::Event(kind, len) => Self::Event(f(kind), len + offset),
+            Self::Incomplete => Self::Incomplete,
+        }
+    }
+}
+
+fn parse_event(bytes: &[u8]) -> Parsed {
+    match bytes {
+        [] | [b'\x1b'] => Parsed::Incomplete,
+
+        // Linux console F1 to F5
+        [b'\x1b', b'[', b'['] => Parsed::Incomplete,
+        [b'\x1b', b'[', b'[', key @ b'A'..=b'E', ..] => {
+            Parsed::Event(EventKind::just_key(KeyCode::Fn(key - b'A' + 1)), 4)
         }
 
-        _ => decode_bytes(bytes)?,
+        [b'\x1b', b'[', rest @ ..] => parse_csi(rest).map(|kind| kind, 2),
+
+        [b'\x1b', b'O', rest @ ..] => parse_ss3(rest).map(|kind| kind, 2),
+
+        // An escape before another sequence, rather than an alt prefix.
+        [b'\x1b', b'\x1b', ..] => Parsed::Event(EventKind::just_key(KeyCode::Escape), 1),
+
+        [b'\x1b', rest @ ..] => parse_char(rest).map(with_alt, 1),
+
+        _ => parse_char(bytes),
+    }
+}
+
+/// Parse pasted text, following the [`PASTE_START`], searching for its end
+/// from `from`.
+fn parse_paste(bytes: &[u8], from: usize) -> Parsed {
+    let Some(end) = bytes[from..]
+        .windows(PASTE_END.len())
+        .position(|window| window == PASTE_END)
+        .map(|end| from + end)
+    else {
+        return Parsed::Incomplete;
     };
 
-    Some(event)
+    Parsed::Event(
+        EventKind::Paste(paste_text(&bytes[..end])),
+        end + PASTE_END.len(),
+    )
 }
 
-fn parse_modifiers(bytes: &[u8]) -> Option<Modifiers> {
-    std::str::from_utf8(bytes)
-        .ok()
-        .and_then(|s| s.parse::<u8>().ok())
-        .map(|byte| Modifiers::from_bits_truncate(byte.saturating_sub(1)))
+fn paste_text(bytes: &[u8]) -> String {
+    // Terminals send newlines as carriage returns, as if they were typed.
+    String::from_utf8_lossy(bytes)
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
 }
 
-/// Decode a character, handling the control keys,
-/// control characters* and utf-8.
-///
-/// Panics if the first byte isn't present.
+fn with_alt(kind: EventKind) -> EventKind {
+    match kind {
+        EventKind::Key(mut key_event) => {
+            key_event.modifiers |= Modifiers::ALT;
+            EventKind::Key(key_event)
+        }
+        kind => kind,
+    }
+}
+
+/// Parse a control sequence, following the `ESC [`.
+fn parse_csi(bytes: &[u8]) -> Parsed {
+    // Parameter and intermediate bytes, followed by a final byte. rxvt ends
+    // shifted keys with `$`, which is really an intermediate byte.
+    let Some(end) = bytes
+        .iter()
+        .position(|&byte| byte == b'$' || !(0x20..=0x3f).contains(&byte))
+    else {
+        return Parsed::Incomplete;
+    };
+
+    let (params, final_byte) = (&bytes[..end], bytes[end]);
+
+    if !(0x40..=0x7e).contains(&final_byte) && final_byte != b'$' {
+        // Not a sequence at all, so it must have been alt-[.
+        let kind = if params.is_empty() {
+            EventKind::Key(KeyEvent::new(KeyCode::Char('['), Modifiers::ALT))
+        } else {
+            EventKind::Unknown
+        };
+
+        return Parsed::Event(kind, end);
+    }
+
+    let kind = match params {
+        [b'<', params @ ..] => parse_mouse(params, final_byte),
+        [b'?', ..] if final_byte == b'u' => Some(EventKind::KeyboardProtocol),
+        [] if final_byte == b'I' => Some(EventKind::FocusGained),
+        [] if final_byte == b'O' => Some(EventKind::FocusLost),
+        _ => parse_csi_key(params, final_byte).map(EventKind::Key),
+    };
+
+    Parsed::Event(kind.unwrap_or(EventKind::Unknown), end + 1)
+}
+
+fn parse_csi_key(params: &[u8], final_byte: u8) -> Option<KeyEvent> {
+    let mut params = params.split(|&byte| byte == b';');
+    let key_code = params.next().unwrap_or_default();
+
+    // Any text that follows the modifiers is ignored.
+    let (mut modifiers, kind) = match params.next() {
+        Some(modifiers) => parse_modifiers(modifiers)?,
+        None => (Modifiers::empty(), KeyEventKind::Press),
+    };
+
+    let key_code = match final_byte {
+        b'u' => return parse_kitty_key(key_code, modifiers, kind),
+
+        // vt sequence, where rxvt uses the last byte for the modifiers
+        b'~' | b'$' | b'^' | b'@' => {
+            modifiers |= match final_byte {
+                b'$' => Modifiers::SHIFT,
+                b'^' => Modifiers::CTRL,
+                b'@' => Modifiers::CTRL | Modifiers::SHIFT,
+                _ => Modifiers::empty(),
+            };
+
+            vt_key(parse_number(key_code)?)?
+        }
+
+        // shift is implied
+        b'Z' => {
+            modifiers.remove(Modifiers::SHIFT);
+            KeyCode::BackTab
+        }
+
+        // rxvt shifted arrows
+        b'a'..=b'd' => {
+            modifiers |= Modifiers::SHIFT;
+            letter_key(final_byte.to_ascii_uppercase())?
+        }
+
+        // xterm sequence
+        _ => letter_key(final_byte)?,
+    };
+
+    Some(KeyEvent {
+        key_code,
+        modifiers,
+        kind,
+    })
+}
+
+/// The key for a vt sequence's number.
 ///
-/// *Not to be confused with one another.
-fn decode_bytes(bytes: &[u8]) -> Option<EventKind> {
-    match bytes {
-        [] => None,
-        [b] => Some(EventKind::Key(decode_byte(*b))),
-        _ => {
-            let s = std::str::from_utf8(bytes).ok()?;
-            let kind = if s.chars().nth(1).is_some() {
-                EventKind::String(s.to_owned())
-            } else {
-                EventKind::Key(KeyEvent::key(KeyCode::Char(s.chars().next()?)))
+/// F13 to F20 are those of the vt220, which are also what rxvt and the
+/// Linux console send for shifted function keys. Other terminals send
+/// F1 to F12 with modifiers, and only the kitty keyboard protocol can
+/// tell apart every function key.
+fn vt_key(number: u32) -> Option<KeyCode> {
+    let key_code = match number {
+        1 | 7 => KeyCode::Home,
+        2 => KeyCode::Insert,
+        3 => KeyCode::Delete,
+        4 | 8 => KeyCode::End,
+        5 => KeyCode::PageUp,
+        6 => KeyCode::PageDown,
+
+        // the gaps are where the vt220 had spaces between groups of keys
+        11..=15 => KeyCode::Fn(number as u8 - 10),
+        17..=21 => KeyCode::Fn(number as u8 - 11),
+        23..=26 => KeyCode::Fn(number as u8 - 12),
+        28..=29 => KeyCode::Fn(number as u8 - 13),
+        31..=34 => KeyCode::Fn(number as u8 - 14),
+
+        _ => return None,
+    };
+
+    Some(key_code)
+}
+
+/// The key for the last byte of an xterm or SS3 sequence.
+fn letter_key(byte: u8) -> Option<KeyCode> {
+    let key_code = match byte {
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+
+        b'F' => KeyCode::End,
+        b'H' => KeyCode::Home,
+
+        b'P' => KeyCode::Fn(1),
+        b'Q' => KeyCode::Fn(2),
+        b'R' => KeyCode::Fn(3),
+        b'S' => KeyCode::Fn(4),
+
+        _ => return None,
+    };
+
+    Some(key_code)
+}
+
+/// Parse a key sent with SS3, following the `ESC O`. Sent in application
+/// cursor and keypad modes, and by some terminals for F1 to F4.
+fn parse_ss3(bytes: &[u8]) -> Parsed {
+    // Some terminals put the modifiers before the key.
+    let digits = bytes
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    let Some(&final_byte) = bytes.get(digits) else {
+        return Parsed::Incomplete;
+    };
+
+    if !(0x40..=0x7e).contains(&final_byte) {
+        // Not a sequence at all, so it must have been alt-O.
+        let kind = match digits {
+            0 => EventKind::Key(KeyEvent::new(KeyCode::Char('O'), Modifiers::ALT)),
+            _ => EventKind::Unknown,
+        };
+
+        return Parsed::Event(kind, digits);
+    }
+
+    let modifiers = match digits {
+        0 => Some(Modifiers::empty()),
+        _ => parse_modifiers(&bytes[..digits]).map(|(modifiers, _)| modifiers),
+    };
+
+    let key_event = modifiers.and_then(|modifiers| {
+        let (key_code, modifiers) = match final_byte {
+            // rxvt ctrl arrows
+            b'a'..=b'd' => (
+                letter_key(final_byte.to_ascii_uppercase())?,
+                modifiers | Modifiers::CTRL,
+            ),
+
+            // keypad
+            b'M' => (KeyCode::Return, modifiers),
+            b'X' => (KeyCode::Char('='), modifiers),
+            b'j' => (KeyCode::Char('*'), modifiers),
+            b'k' => (KeyCode::Char('+'), modifiers),
+            b'l' => (KeyCode::Char(','), modifiers),
+            b'm' => (KeyCode::Char('-'), modifiers),
+            b'n' => (KeyCode::Char('.'), modifiers),
+            b'o' => (KeyCode::Char('/'), modifiers),
+            b'p'..=b'y' => (KeyCode::Char((final_byte - b'p' + b'0') as char), modifiers),
+
+            _ => (letter_key(final_byte)?, modifiers),
+        };
+
+        Some(KeyEvent::new(key_code, modifiers))
+    });
+
+    Parsed::Event(
+        key_event.map_or(EventKind::Unknown, EventKind::Key),
+        digits + 1,
+    )
+}
+
+/// Parse a key from the kitty keyboard protocol, given its key codes: the
+/// unshifted key, then optionally the shifted key.
+fn parse_kitty_key(
+    key_codes: &[u8],
+    mut modifiers: Modifiers,
+    kind: KeyEventKind,
+) -> Option<KeyEvent> {
+    let mut key_codes = key_codes.split(|&byte| byte == b':').map(parse_number);
+    let key = key_codes.next().flatten()?;
+    let shifted_key = key_codes.next().flatten();
+
+    let key_code = match key {
+        9 if modifiers.contains(Modifiers::SHIFT) => {
+            modifiers.remove(Modifiers::SHIFT);
+            KeyCode::BackTab
+        }
+        9 => KeyCode::Tab,
+        13 => KeyCode::Return,
+        27 => KeyCode::Escape,
+        127 => KeyCode::Backspace,
+
+        // the keys without a unicode equivalent are in the private use area
+        57376..=57398 => KeyCode::Fn((key - 57376 + 13) as u8),
+
+        // keypad
+        57399..=57408 => KeyCode::Char((b'0' + (key - 57399) as u8) as char),
+        57409 => KeyCode::Char('.'),
+        57410 => KeyCode::Char('/'),
+        57411 => KeyCode::Char('*'),
+        57412 => KeyCode::Char('-'),
+        57413 => KeyCode::Char('+'),
+        57414 => KeyCode::Return,
+        57415 => KeyCode::Char('='),
+        57417 => KeyCode::Left,
+        57418 => KeyCode::Right,
+        57419 => KeyCode::Up,
+        57420 => KeyCode::Down,
+        57421 => KeyCode::PageUp,
+        57422 => KeyCode::PageDown,
+        57423 => KeyCode::Home,
+        57424 => KeyCode::End,
+        57425 => KeyCode::Insert,
+        57426 => KeyCode::Delete,
+
+        // media keys, the modifier keys themselves, etc.
+        57344..=63743 => return None,
+
+        key => {
+            let c = char::from_u32(key)?;
+
+            // Shift is reflected in the character, as it is for typed text.
+            let c = match shifted_key.and_then(char::from_u32) {
+                Some(shifted) if modifiers.contains(Modifiers::SHIFT) => shifted,
+                _ if modifiers.contains(Modifiers::SHIFT) => c.to_ascii_uppercase(),
+                _ => c,
             };
-            Some(kind)
+
+            KeyCode::Char(c)
         }
+    };
+
+    Some(KeyEvent {
+        key_code,
+        modifiers,
+        kind,
+    })
+}
+
+fn parse_number(bytes: &[u8]) -> Option<u32> {
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Parse an SGR mouse sequence, following the `ESC [ <`.
+fn parse_mouse(params: &[u8], final_byte: u8) -> Option<EventKind> {
+    let params = std::str::from_utf8(params).ok()?;
+    let mut params = params.split(';').map(|param| param.parse::<usize>().ok());
+
+    let (Some(Some(code)), Some(Some(x)), Some(Some(y)), None) =
+        (params.next(), params.next(), params.next(), params.next())
+    else {
+        return None;
+    };
+
+    let mut modifiers = Modifiers::empty();
+    modifiers.set(Modifiers::SHIFT, code & 4 != 0);
+    modifiers.set(Modifiers::ALT, code & 8 != 0);
+    modifiers.set(Modifiers::CTRL, code & 16 != 0);
+
+    let button = match code & 0b11 {
+        0 => Some(MouseButton::Left),
+        1 => Some(MouseButton::Middle),
+        2 => Some(MouseButton::Right),
+        _ => None,
+    };
+
+    let (kind, button) = match (code & !0b11111, final_byte) {
+        (0, b'M') => (MouseKind::Press, button),
+        (0, b'm') => (MouseKind::Release, button),
+        (32, b'M') => (MouseKind::Drag, Some(button?)),
+
+        (64, b'M') => {
+            let kind = match code & 0b11 {
+                0 => MouseKind::ScrollUp,
+                1 => MouseKind::ScrollDown,
+                2 => MouseKind::ScrollLeft,
+                _ => MouseKind::ScrollRight,
+            };
+            (kind, None)
+        }
+
+        // extra buttons, or movement without a button held
+        _ => return None,
+    };
+
+    Some(EventKind::Mouse {
+        kind,
+        button,
+        x: x.checked_sub(1)?,
+        y: y.checked_sub(1)?,
+        modifiers,
+    })
+}
+
+/// Parse the modifiers parameter, which is one more than a bit set of the
+/// modifiers, and optionally the kind of key event.
+fn parse_modifiers(bytes: &[u8]) -> Option<(Modifiers, KeyEventKind)> {
+    let mut params = bytes.split(|&byte| byte == b':').map(parse_number);
+
+    let bits = params.next().flatten()?.saturating_sub(1);
+
+    // Shift, alt, ctrl and super are in the same order as `Modifiers`, and
+    // the kitty keyboard protocol adds hyper and meta after them.
+    let mut modifiers = Modifiers::from_bits_truncate((bits & 0b1111) as u8);
+    if bits & 0b10_0000 != 0 {
+        modifiers |= Modifiers::META;
     }
+
+    let kind = match params.next().flatten() {
+        None | Some(1) => KeyEventKind::Press,
+        Some(2) => KeyEventKind::Repeat,
+        Some(3) => KeyEventKind::Release,
+        Some(_) => return None,
+    };
+
+    Some((modifiers, kind))
 }
 
+/// Parse a character, handling the control keys,
+/// control characters* and utf-8.
+///
+/// *Not to be confused with one another.
+fn parse_char(bytes: &[u8]) -> Parsed {
+    let Some(&first) = bytes.first() else {
+        return Parsed::Incomplete;
+    };
+
+    if first.is_ascii() {
+        return Parsed::Event(EventKind::Key(decode_byte(first)), 1);
+    }
+
+    let len = match first.leading_ones() {
+        len @ 2..=4 => len as usize,
+        _ => return Parsed::Event(EventKind::Unknown, 1),
+    };
+
+    // Don't wait for the rest of a character that is already invalid.
+    let continuation = &bytes[1..bytes.len().min(len)];
+    if !continuation.iter().all(|&byte| byte & 0xc0 == 0x80) {
+        return Parsed::Event(EventKind::Unknown, 1);
+    }
+
+    let Some(bytes) = bytes.get(..len) else {
+        return Parsed::Incomplete;
+    };
+
+    let kind = std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.chars().next())
+        .map_or(EventKind::Unknown, |c| {
+            EventKind::Key(KeyEvent::key(KeyCode::Char(c)))
+        });
+
+    Parsed::Event(kind, len)
+}
+
+/// Decode a byte the legacy way, where control keys and control
+/// characters share the same bytes: Ctrl-I is Tab, Ctrl-M is Return, etc.
 fn decode_byte(byte: u8) -> KeyEvent {
     match byte {
         b'\t' => KeyEvent::key(KeyCode::Tab),
         b'\n' => KeyEvent::key(KeyCode::Newline),
         b'\r' => KeyEvent::key(KeyCode::Return),
-
         b'\x7f' => KeyEvent::key(KeyCode::Backspace),
-        b'\x17' => KeyEvent {
-            key_code: KeyCode::Backspace,
-            modifiers: Modifiers::CTRL,
-        },
 
-        b if b < 27 => KeyEvent {
-            key_code: KeyCode::Char((b'A' + b - 1) as char),
-            modifiers: Modifiers::CTRL,
-        },
+        b'\0' => KeyEvent::new(KeyCode::Char(' '), Modifiers::CTRL),
+        b'\x01'..=b'\x1a' => {
+            KeyEvent::new(KeyCode::Char((b'a' + byte - 1) as char), Modifiers::CTRL)
+        }
+        b'\x1c'..=b'\x1f' => KeyEvent::new(KeyCode::Char((byte + 0x40) as char), Modifiers::CTRL),
+
+        b'A'..=b'Z' => KeyEvent::new(KeyCode::Char(byte as char), Modifiers::SHIFT),
 
         _ => KeyEvent::key(KeyCode::Char(byte as char)),
     }
@@ -272,3 +825,262 @@ fn decode_byte(byte: u8) -> KeyEvent {
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key_code: KeyCode, modifiers: Modifiers) -> EventKind {
+        EventKind::Key(KeyEvent::new(key_code, modifiers))
+    }
+
+    fn chars(s: &str) -> Vec<EventKind> {
+        s.chars()
+            .map(|c| key(KeyCode::Char(c), Modifiers::empty()))
+            .collect()
+    }
+
+    fn decode(decoder: &mut Decoder, bytes: &[u8]) -> Vec<EventKind> {
+        decoder.feed(bytes);
+        decoder.by_ref().map(|event| event.kind).collect()
+    }
+
+    #[test]
+    fn split_and_batched_reads() {
+        let mut decoder = Decoder::default();
+
+        // several events in one read
+        let mut expected = chars(":w");
+        expected.push(key(KeyCode::Return, Modifiers::empty()));
+        assert_eq!(decode(&mut decoder, b":w\r"), expected);
+
+        // a sequence split across reads
+        assert_eq!(decode(&mut decoder, b"a\x1b[1;"), chars("a"));
+        assert!(decoder.incomplete_since().is_some());
+        assert_eq!(
+            decode(&mut decoder, b"5Ab"),
+            [key(KeyCode::Up, Modifiers::CTRL), chars("b").remove(0)]
+        );
+
+        // a character split across reads
+        assert_eq!(decode(&mut decoder, b"\xc3"), []);
+        assert_eq!(decode(&mut decoder, b"\xa9"), chars("é"));
+        assert!(decoder.incomplete_since().is_none());
+
+        // a modified vt sequence
+        assert_eq!(
+            decode(&mut decoder, b"\x1b[3;2~"),
+            [key(KeyCode::Delete, Modifiers::SHIFT)]
+        );
+    }
+
+    #[test]
+    fn escape_and_alt() {
+        let mut decoder = Decoder::default();
+
+        assert_eq!(
+            decode(&mut decoder, b"\x1bx"),
+            [key(KeyCode::Char('x'), Modifiers::ALT)]
+        );
+
+        assert_eq!(
+            decode(&mut decoder, b"\x1b\x1b[B"),
+            [
+                key(KeyCode::Escape, Modifiers::empty()),
+                key(KeyCode::Down, Modifiers::empty())
+            ]
+        );
+
+        // a lone escape waits until flushed
+        assert_eq!(decode(&mut decoder, b"\x1b"), []);
+        assert_eq!(
+            decoder.flush().map(|event| event.kind),
+            Some(key(KeyCode::Escape, Modifiers::empty()))
+        );
+
+        // as does the start of a sequence, which was alt-[ after all
+        assert_eq!(decode(&mut decoder, b"\x1b["), []);
+        assert_eq!(
+            decoder.flush().map(|event| event.kind),
+            Some(key(KeyCode::Char('['), Modifiers::ALT))
+        );
+        assert!(decoder.flush().is_none());
+    }
+
+    #[test]
+    fn bracketed_paste() {
+        let mut decoder = Decoder::default();
+
+        assert_eq!(decode(&mut decoder, b"a\x1b[200~one\r"), chars("a"));
+        assert!(decoder.incomplete_since().is_some());
+        assert_eq!(decode(&mut decoder, b"\x1b[A\rtwo\x1b[20"), []);
+
+        let mut expected = vec![EventKind::Paste("one\n\x1b[A\ntwo".to_owned())];
+        expected.extend(chars("b"));
+        assert_eq!(decode(&mut decoder, b"1~b"), expected);
+
+        // a paste that never ends is given up on like any other sequence
+        assert_eq!(decode(&mut decoder, b"\x1b[200~three\r"), []);
+        assert_eq!(
+            decoder.flush().map(|event| event.kind),
+            Some(EventKind::Paste("three\n".to_owned()))
+        );
+        assert!(decoder.flush().is_none());
+    }
+
+    #[test]
+    fn mouse() {
+        let mut decoder = Decoder::default();
+
+        assert_eq!(
+            decode(
+                &mut decoder,
+                b"\x1b[<0;3;5M\x1b[<48;4;5M\x1b[<0;4;5m\x1b[<65;1;1M"
+            ),
+            [
+                EventKind::Mouse {
+                    kind: MouseKind::Press,
+                    button: Some(MouseButton::Left),
+                    x: 2,
+                    y: 4,
+                    modifiers: Modifiers::empty(),
+                },
+                EventKind::Mouse {
+                    kind: MouseKind::Drag,
+                    button: Some(MouseButton::Left),
+                    x: 3,
+                    y: 4,
+                    modifiers: Modifiers::CTRL,
+                },
+                EventKind::Mouse {
+                    kind: MouseKind::Release,
+                    button: Some(MouseButton::Left),
+                    x: 3,
+                    y: 4,
+                    modifiers: Modifiers::empty(),
+                },
+                EventKind::Mouse {
+                    kind: MouseKind::ScrollDown,
+                    button: None,
+                    x: 0,
+                    y: 0,
+                    modifiers: Modifiers::empty(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn kitty_keyboard() {
+        let mut decoder = Decoder::default();
+
+        let release = |key_code, modifiers| {
+            EventKind::Key(KeyEvent {
+                key_code,
+                modifiers,
+                kind: KeyEventKind::Release,
+            })
+        };
+
+        assert_eq!(
+            decode(
+                &mut decoder,
+                b"\x1b[105;5u\x1b[97:65;2u\x1b[27u\x1b[114;5:3u\x1b[1;1:2A\x1b[97;33u\x1b[?7u"
+            ),
+            [
+                key(KeyCode::Char('i'), Modifiers::CTRL),
+                key(KeyCode::Char('A'), Modifiers::SHIFT),
+                key(KeyCode::Escape, Modifiers::empty()),
+                release(KeyCode::Char('r'), Modifiers::CTRL),
+                EventKind::Key(KeyEvent {
+                    key_code: KeyCode::Up,
+                    modifiers: Modifiers::empty(),
+                    kind: KeyEventKind::Repeat,
+                }),
+                key(KeyCode::Char('a'), Modifiers::META),
+                EventKind::KeyboardProtocol,
+            ]
+        );
+
+        // the legacy encoding can't tell some keys apart
+        assert_eq!(
+            decode(&mut decoder, b"\t\x17R"),
+            [
+                key(KeyCode::Tab, Modifiers::empty()),
+                key(KeyCode::Char('w'), Modifiers::CTRL),
+                key(KeyCode::Char('R'), Modifiers::SHIFT),
+            ]
+        );
+    }
+
+    #[test]
+    fn terminal_sequences() {
+        use KeyCode::*;
+
+        let none = Modifiers::empty();
+        let shift = Modifiers::SHIFT;
+        let alt = Modifiers::ALT;
+        let ctrl = Modifiers::CTRL;
+
+        let table: &[(&str, &[u8], KeyCode, Modifiers)] = &[
+            ("xterm", b"\x1bOP", Fn(1), none),
+            ("xterm", b"\x1b[1;2P", Fn(1), shift),
+            ("xterm", b"\x1b[15~", Fn(5), none),
+            ("xterm", b"\x1b[24~", Fn(12), none),
+            ("xterm", b"\x1b[24;5~", Fn(12), ctrl),
+            ("xterm", b"\x1b[A", Up, none),
+            ("xterm", b"\x1bOA", Up, none),
+            ("xterm", b"\x1b[1;3C", Right, alt),
+            ("xterm", b"\x1b[1;9A", Up, Modifiers::META),
+            ("xterm", b"\x1b[H", Home, none),
+            ("xterm", b"\x1bOF", End, none),
+            ("xterm", b"\x1b[1;5H", Home, ctrl),
+            ("xterm", b"\x1b[3~", Delete, none),
+            ("xterm", b"\x1b[Z", BackTab, none),
+            ("xterm", b"\x1bOM", Return, none),
+            ("xterm", b"\x1bOp", Char('0'), none),
+            ("rxvt", b"\x1b[11~", Fn(1), none),
+            ("rxvt", b"\x1b[23~", Fn(11), none),
+            ("rxvt", b"\x1b[25~", Fn(13), none),
+            ("rxvt", b"\x1b[34~", Fn(20), none),
+            ("rxvt", b"\x1b[23$", Fn(11), shift),
+            ("rxvt", b"\x1b[11^", Fn(1), ctrl),
+            ("rxvt", b"\x1b[2$", Insert, shift),
+            ("rxvt", b"\x1b[7~", Home, none),
+            ("rxvt", b"\x1b[8^", End, ctrl),
+            ("rxvt", b"\x1b[5@", PageUp, ctrl | shift),
+            ("rxvt", b"\x1b[a", Up, shift),
+            ("rxvt", b"\x1bOd", Left, ctrl),
+            ("tmux", b"\x1b[1~", Home, none),
+            ("tmux", b"\x1b[4~", End, none),
+            ("tmux", b"\x1bOQ", Fn(2), none),
+            ("tmux", b"\x1b[1;5D", Left, ctrl),
+            ("tmux", b"\x1b[6;2~", PageDown, shift),
+            ("tmux", b"\x1b[19;3~", Fn(8), alt),
+            ("tmux", b"\x1b[Z", BackTab, none),
+            ("screen", b"\x1bOR", Fn(3), none),
+            ("screen", b"\x1b[20~", Fn(9), none),
+            ("screen", b"\x1b[D", Left, none),
+            ("screen", b"\x1b[2~", Insert, none),
+            ("linux", b"\x1b[[A", Fn(1), none),
+            ("linux", b"\x1b[[E", Fn(5), none),
+            ("linux", b"\x1b[17~", Fn(6), none),
+            ("linux", b"\x1b[25~", Fn(13), none),
+            ("linux", b"\x1b[3~", Delete, none),
+            ("linux", b"\x7f", Backspace, none),
+            ("linux", b"\x1bx", Char('x'), alt),
+            ("konsole", b"\x1bO2P", Fn(1), shift),
+            ("kitty", b"\x1b[57387u", Fn(24), none),
+            ("kitty", b"\x1b[9;2u", BackTab, none),
+        ];
+
+        for &(terminal, bytes, key_code, modifiers) in table {
+            let mut decoder = Decoder::default();
+            assert_eq!(
+                decode(&mut decoder, bytes),
+                [key(key_code, modifiers)],
+                "{terminal}: {bytes:?}"
+            );
+        }
+    }
+}
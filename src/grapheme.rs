@@ -0,0 +1,191 @@
+//! Grapheme clusters: what the user sees as a single character, such as a
+//! letter with combining accents or an emoji made of several code points.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use ropey::{Rope, RopeSlice};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// The most bytes a [`Grapheme`] can hold, which is enough for all but the
+/// longest emoji sequences.
+const CAPACITY: usize = 30;
+
+/// A grapheme cluster stored inline, so that cells stay `Copy`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Grapheme {
+    len: u8,
+    bytes: [u8; CAPACITY],
+}
+
+impl Grapheme {
+    /// Fills the cells covered by the rest of a wide grapheme.
+    pub const CONTINUATION: Self = Self {
+        len: 0,
+        bytes: [0; CAPACITY],
+    };
+
+    /// A grapheme that is too long to store is replaced with U+FFFD.
+    pub fn new(grapheme: &str) -> Self {
+        let grapheme = match grapheme.len() {
+            0 => " ",
+            len if len > CAPACITY => "\u{fffd}",
+            _ => grapheme,
+        };
+
+        let mut bytes = [0; CAPACITY];
+        bytes[..grapheme.len()].copy_from_slice(grapheme.as_bytes());
+
+        Self {
+            len: grapheme.len() as u8,
+            bytes,
+        }
+    }
+
+    pub fn from_char(c: char) -> Self {
+        Self::new(c.encode_utf8(&mut [0; 4]))
+    }
+
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
+
+    pub fn is_continuation(&self) -> bool {
+        self.len == 0
+    }
+
+    /// How many columns the grapheme takes up, which is 0 for a
+    /// continuation.
+    pub fn width(&self) -> usize {
+        match self.is_continuation() {
+            true => 0,
+            false => width(self.as_str()),
+        }
+    }
+}
+
+impl fmt::Debug for Grapheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.is_continuation() {
+            true => f.write_str("Continuation"),
+            false => fmt::Debug::fmt(self.as_str(), f),
+        }
+    }
+}
+
+/// How many columns a grapheme takes up: 1 or 2.
+///
+/// Graphemes with no width of their own, such as a combining mark with
+/// nothing to combine with, still get a cell so that they can be seen.
+pub fn width(grapheme: &str) -> usize {
+    if grapheme.starts_with(char::is_control) {
+        return 1;
+    }
+
+    grapheme.width().clamp(1, 2)
+}
+
+/// How many columns a string takes up.
+pub fn str_width(s: &str) -> usize {
+    s.graphemes(true).map(width).sum()
+}
+
+/// How many columns part of a rope takes up.
+pub fn slice_width(slice: RopeSlice) -> usize {
+    str_width(&Cow::from(slice))
+}
+
+/// The offset in chars of the grapheme that covers `column`, or the length
+/// of the string in chars if it is too short.
+pub fn offset_at_column(s: &str, column: usize) -> usize {
+    let mut offset = 0;
+    let mut end_column = 0;
+
+    for grapheme in s.graphemes(true) {
+        end_column += width(grapheme);
+        if end_column > column {
+            break;
+        }
+        offset += grapheme.chars().count();
+    }
+
+    offset
+}
+
+/// The position of the next grapheme after the one at `pos`.
+pub fn next_boundary(rope: &Rope, pos: usize) -> usize {
+    if pos >= rope.len_chars() {
+        return rope.len_chars();
+    }
+
+    let y = rope.char_to_line(pos);
+    let line_start = rope.line_to_char(y);
+    let line = Cow::from(rope.line(y));
+
+    let mut end = line_start;
+    for grapheme in line.graphemes(true) {
+        end += grapheme.chars().count();
+        if end > pos {
+            break;
+        }
+    }
+
+    end
+}
+
+/// The position of the grapheme before `pos`.
+pub fn prev_boundary(rope: &Rope, pos: usize) -> usize {
+    let pos = pos.min(rope.len_chars());
+    if pos == 0 {
+        return 0;
+    }
+
+    let y = rope.char_to_line(pos - 1);
+    let line_start = rope.line_to_char(y);
+    let line = Cow::from(rope.line(y));
+
+    let mut start = line_start;
+    let mut prev_start = line_start;
+    for grapheme in line.graphemes(true) {
+        if start >= pos {
+            break;
+        }
+        prev_start = start;
+        start += grapheme.chars().count();
+    }
+
+    prev_start
+}
+
+#[cfg(test)]
+mod tests {
+    use ropey::Rope;
+
+    use super::*;
+
+    #[test]
+    fn graphemes() {
+        assert_eq!(Grapheme::new("e\u{301}").width(), 1);
+        assert_eq!(Grapheme::from_char('漢').width(), 2);
+        assert_eq!(Grapheme::new("👍🏽").width(), 2);
+        assert_eq!(Grapheme::CONTINUATION.width(), 0);
+        assert_eq!(Grapheme::new(&"a\u{301}".repeat(20)).as_str(), "\u{fffd}");
+
+        assert_eq!(str_width("a漢e\u{301}"), 4);
+        assert_eq!(offset_at_column("a漢e\u{301}b", 2), 1);
+        assert_eq!(offset_at_column("a漢e\u{301}b", 4), 4);
+        assert_eq!(offset_at_column("a漢", 10), 2);
+
+        // "e" and a combining accent, then a flag made of two code points
+        let rope = Rope::from_str("e\u{301}🇬🇧\nx");
+        assert_eq!(next_boundary(&rope, 0), 2);
+        assert_eq!(next_boundary(&rope, 2), 4);
+        assert_eq!(next_boundary(&rope, 4), 5);
+        assert_eq!(next_boundary(&rope, 6), 6);
+        assert_eq!(prev_boundary(&rope, 5), 4);
+        assert_eq!(prev_boundary(&rope, 4), 2);
+        assert_eq!(prev_boundary(&rope, 3), 2);
+        assert_eq!(prev_boundary(&rope, 2), 0);
+    }
+}
@@ -3,28 +3,47 @@ use std::time::Instant;
 use std::{fmt, io, thread};
 
 use crossbeam_channel::{Receiver, RecvTimeoutError};
+use libc::c_int;
 
-pub struct PollingStdin {
-    recv: Receiver<io::Result<Bytes>>,
+use crate::signal::SignalPipe;
+
+/// The signals that are turned into input.
+const SIGNALS: &[c_int] = &[libc::SIGWINCH, libc::SIGTERM, libc::SIGHUP, libc::SIGCONT];
+
+#[derive(Debug, Clone)]
+pub enum Input {
+    Bytes(Bytes),
+    Signal(c_int),
 }
 
-#[derive(Default, Clone)]
-pub struct Bytes {
-    len: usize,
-    buf: [u8; 32],
+/// Reads stdin and signals on background threads.
+pub struct PollingInput {
+    recv: Receiver<io::Result<Input>>,
 }
 
+/// How much of stdin is read at once.
+const READ_SIZE: usize = 1024;
+
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct Bytes(Vec<u8>);
+
 impl Bytes {
     pub fn as_slice(&self) -> &[u8] {
-        &self.buf[..self.len]
+        &self.0
     }
 
     pub fn len(&self) -> usize {
-        self.len
+        self.0.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.len == 0
+        self.0.is_empty()
+    }
+}
+
+impl From<&[u8]> for Bytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
     }
 }
 
@@ -35,27 +54,31 @@ impl fmt::Debug for Bytes {
     }
 }
 
-impl Default for PollingStdin {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl PollingStdin {
-    pub fn new() -> Self {
+impl PollingInput {
+    pub fn new() -> io::Result<Self> {
         let (send, recv) = crossbeam_channel::bounded(8);
 
+        let signals = SignalPipe::new(SIGNALS)?;
+        let send_signal = send.clone();
+        thread::spawn(move || loop {
+            let signal = signals.wait().map(Input::Signal);
+            let failed = signal.is_err();
+
+            if send_signal.send(signal).is_err() || failed {
+                break;
+            }
+        });
+
         let mut stdin = io::stdin();
         thread::spawn(move || {
-            loop {
-                let mut bytes = Bytes::default();
+            let mut buf = [0; READ_SIZE];
 
-                match stdin.read(&mut bytes.buf) {
+            loop {
+                match stdin.read(&mut buf) {
                     // Some bytes were written, so send them to the main thread.
-                    Ok(len) => {
-                        bytes.len = len;
-                        send.send(Ok(bytes)).unwrap()
-                    }
+                    Ok(len) => send
+                        .send(Ok(Input::Bytes(Bytes::from(&buf[..len]))))
+                        .unwrap(),
 
                     // Interrupted - continue reading.
                     Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
@@ -66,12 +89,18 @@ impl PollingStdin {
             }
         });
 
-        Self { recv }
+        Ok(Self { recv })
     }
 
-    pub fn read_with_deadline(&self, deadline: Instant) -> io::Result<Option<Bytes>> {
-        match self.recv.recv_deadline(deadline) {
-            Ok(bytes) => bytes.map(Some),
+    /// Wait for input until the deadline, or indefinitely if there is none.
+    pub fn read_with_deadline(&self, deadline: Option<Instant>) -> io::Result<Option<Input>> {
+        let res = match deadline {
+            Some(deadline) => self.recv.recv_deadline(deadline),
+            None => self.recv.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match res {
+            Ok(input) => input.map(Some),
             Err(RecvTimeoutError::Timeout) => Ok(None),
             Err(RecvTimeoutError::Disconnected) => panic!("sender disconnected"),
         }
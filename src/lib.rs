@@ -1,7 +1,9 @@
 pub mod buffer;
 // pub mod command;
 pub mod event;
+pub mod grapheme;
 pub mod input;
+pub mod signal;
 pub mod style;
 pub mod term;
 // pub mod widget;
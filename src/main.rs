@@ -1,15 +1,18 @@
-use std::time::Duration;
-
 use text_editor::editor::{EditorRoot, EditorState};
 use text_editor::ui::*;
 
-const REFRESH_RATE: Duration = Duration::from_millis(17);
-
 fn main() {
     setup_logger().expect("failed to initialize logger");
 
-    let widget = EditorRoot::default();
-    let app = App::new(EditorState::default(), widget, REFRESH_RATE).expect("couldn't create app");
+    let widget = match std::env::args_os().nth(1) {
+        Some(path) => EditorRoot::open(&path).unwrap_or_else(|err| {
+            eprintln!("{}: {err}", path.to_string_lossy());
+            std::process::exit(1);
+        }),
+        None => EditorRoot::default(),
+    };
+
+    let app = App::new(EditorState::default(), widget).expect("couldn't create app");
     app.run().expect("IO error");
 }
 
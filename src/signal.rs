@@ -0,0 +1,109 @@
+use std::io;
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use libc::c_int;
+
+use crate::term::cvt;
+
+#[cfg(any(target_os = "linux", target_os = "emscripten", target_os = "dragonfly"))]
+use libc::__errno_location as errno_location;
+
+#[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+use libc::__errno as errno_location;
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+use libc::__error as errno_location;
+
+/// The write end of the pipe, for the signal handler.
+static PIPE_WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handle_signal(signal: c_int) {
+    let fd = PIPE_WRITE_FD.load(Ordering::Relaxed);
+    if fd >= 0 {
+        // Only async-signal-safe functions may be called here. If the pipe
+        // is full the signal is dropped, which is fine as there are
+        // already signals waiting to be read.
+        let byte = signal as u8;
+        unsafe {
+            // The code that was interrupted may be about to look at errno.
+            let errno = *errno_location();
+            libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+            *errno_location() = errno;
+        }
+    }
+}
+
+/// Turns signals into reads from a pipe, so that they can be handled
+/// outside of a signal handler.
+pub struct SignalPipe {
+    read_fd: RawFd,
+    write_fd: RawFd,
+}
+
+impl SignalPipe {
+    /// Install handlers for the given signals.
+    ///
+    /// Only one pipe receives signals at a time: creating another
+    /// redirects the signals to it.
+    pub fn new(signals: &[c_int]) -> io::Result<Self> {
+        let mut fds = [0; 2];
+        cvt(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+        let [read_fd, write_fd] = fds;
+
+        // pipe2 would set this atomically, but not every platform has it.
+        for fd in fds {
+            cvt(unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) })?;
+        }
+
+        // The handler must never block.
+        cvt(unsafe { libc::fcntl(write_fd, libc::F_SETFL, libc::O_NONBLOCK) })?;
+
+        let old_fd = PIPE_WRITE_FD.swap(write_fd, Ordering::Relaxed);
+        if old_fd >= 0 {
+            unsafe { libc::close(old_fd) };
+        }
+
+        for &signal in signals {
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = handle_signal as extern "C" fn(c_int) as libc::sighandler_t;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+
+                cvt(libc::sigaction(signal, &action, std::ptr::null_mut()))?;
+            }
+        }
+
+        Ok(Self { read_fd, write_fd })
+    }
+
+    /// Block until a signal arrives.
+    pub fn wait(&self) -> io::Result<c_int> {
+        let mut byte = 0u8;
+        loop {
+            match cvt(unsafe {
+                libc::read(self.read_fd, &mut byte as *mut u8 as *mut libc::c_void, 1)
+            }) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) => return Ok(byte as c_int),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for SignalPipe {
+    fn drop(&mut self) {
+        // The write end belongs to the handler until another pipe takes it
+        // over, which closes it then.
+        if PIPE_WRITE_FD
+            .compare_exchange(self.write_fd, -1, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            unsafe { libc::close(self.write_fd) };
+        }
+        unsafe { libc::close(self.read_fd) };
+    }
+}
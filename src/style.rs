@@ -1,16 +1,147 @@
+use bitflags::bitflags;
+
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Color {
-    Black = 0,
-    Red = 1,
-    Greem = 2,
-    Yellow = 3,
-    Blue = 4,
-    Magenta = 5,
-    Cyan = 6,
-    White = 7,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+
+    /// A color from the terminal's palette: the eight colors above, their
+    /// bright versions, a 6x6x6 color cube and then a grayscale ramp.
+    Indexed(u8),
+
+    Rgb(u8, u8, u8),
 
     #[default]
-    Default = 9,
+    Default,
+}
+
+/// The values of the colors in xterm's default palette.
+const PALETTE_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The levels of each component in the 256 color cube.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Color {
+    /// The nearest color that the terminal can show.
+    pub fn downsample(self, support: ColorSupport) -> Self {
+        match (self, support) {
+            (_, ColorSupport::TrueColor) => self,
+
+            (Self::Rgb(r, g, b), ColorSupport::Indexed256) => Self::Indexed(nearest_256(r, g, b)),
+
+            (Self::Rgb(r, g, b), ColorSupport::Ansi16) => Self::Indexed(nearest_16(r, g, b)),
+            (Self::Indexed(index), ColorSupport::Ansi16) if index >= 16 => {
+                let (r, g, b) = index_to_rgb(index);
+                Self::Indexed(nearest_16(r, g, b))
+            }
+
+            _ => self,
+        }
+    }
+}
+
+fn index_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => PALETTE_16[index as usize],
+        16..=231 => {
+            let index = index - 16;
+            (
+                CUBE_LEVELS[(index / 36) as usize],
+                CUBE_LEVELS[(index / 6 % 6) as usize],
+                CUBE_LEVELS[(index % 6) as usize],
+            )
+        }
+        232..=255 => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let dr = r1.abs_diff(r2) as u32;
+    let dg = g1.abs_diff(g2) as u32;
+    let db = b1.abs_diff(b2) as u32;
+    dr * dr + dg * dg + db * db
+}
+
+/// The nearest color in the cube or the grayscale ramp, leaving out the
+/// first 16 colors as they may have been changed by the user.
+fn nearest_256(r: u8, g: u8, b: u8) -> u8 {
+    let nearest_level = |value: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(value))
+            .unwrap() as u8
+    };
+    let cube = 16 + 36 * nearest_level(r) + 6 * nearest_level(g) + nearest_level(b);
+
+    let average = ((r as u32 + g as u32 + b as u32) / 3) as u8;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23);
+
+    [cube, gray]
+        .into_iter()
+        .min_by_key(|&index| distance(index_to_rgb(index), (r, g, b)))
+        .unwrap()
+}
+
+fn nearest_16(r: u8, g: u8, b: u8) -> u8 {
+    (0..16)
+        .min_by_key(|&index| distance(PALETTE_16[index as usize], (r, g, b)))
+        .unwrap()
+}
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSupport {
+    Ansi16,
+    Indexed256,
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Guess from the environment, as there isn't a reliable way to ask.
+    pub fn from_env() -> Self {
+        let colorterm = std::env::var("COLORTERM").ok();
+        let term = std::env::var("TERM").ok();
+        Self::detect(colorterm.as_deref(), term.as_deref())
+    }
+
+    fn detect(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        if let Some("truecolor" | "24bit") = colorterm {
+            return Self::TrueColor;
+        }
+
+        match term {
+            Some(term) if term.ends_with("-direct") || term.contains("truecolor") => {
+                Self::TrueColor
+            }
+            Some(term) if term.contains("256color") => Self::Indexed256,
+            _ => Self::Ansi16,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
@@ -21,11 +152,77 @@ pub enum Weight {
     Dim,
 }
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Underline {
+    #[default]
+    None,
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+bitflags! {
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Attributes: u8 {
+        const ITALIC        = 0b00001;
+        const BLINK         = 0b00010;
+        const REVERSE       = 0b00100;
+        const HIDDEN        = 0b01000;
+        const STRIKETHROUGH = 0b10000;
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Style {
     pub fg: Color,
     pub bg: Color,
 
     pub weight: Weight,
-    pub underline: bool,
+    pub underline: Underline,
+    /// The color of the underline, which is the foreground color by default.
+    pub underline_color: Color,
+    pub attributes: Attributes,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, ColorSupport};
+
+    #[test]
+    fn downsample() {
+        assert_eq!(
+            ColorSupport::detect(Some("truecolor"), Some("xterm")),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            ColorSupport::detect(None, Some("tmux-256color")),
+            ColorSupport::Indexed256
+        );
+        assert_eq!(
+            ColorSupport::detect(None, Some("linux")),
+            ColorSupport::Ansi16
+        );
+
+        let orange = Color::Rgb(255, 135, 0);
+        assert_eq!(orange.downsample(ColorSupport::TrueColor), orange);
+        assert_eq!(
+            orange.downsample(ColorSupport::Indexed256),
+            Color::Indexed(208)
+        );
+        assert_eq!(orange.downsample(ColorSupport::Ansi16), Color::Indexed(3));
+
+        let gray = Color::Rgb(100, 100, 100);
+        assert_eq!(
+            gray.downsample(ColorSupport::Indexed256),
+            Color::Indexed(241)
+        );
+        assert_eq!(
+            Color::Indexed(241).downsample(ColorSupport::Ansi16),
+            Color::Indexed(8)
+        );
+
+        assert_eq!(Color::Red.downsample(ColorSupport::Ansi16), Color::Red);
+    }
 }
@@ -1,4 +1,4 @@
-use std::fmt::Write;
+use std::fmt::{self, Write};
 
 use crate::style::*;
 
@@ -6,15 +6,23 @@ use crate::style::*;
 pub struct AnsiBuilder {
     s: String,
     style: Style,
+    color_support: ColorSupport,
 
     cursor_visible: bool,
 }
 
 impl Default for AnsiBuilder {
     fn default() -> Self {
+        Self::new(ColorSupport::TrueColor)
+    }
+}
+
+impl AnsiBuilder {
+    pub fn new(color_support: ColorSupport) -> Self {
         let mut ansi_builder = AnsiBuilder {
             s: String::new(),
             style: Style::default(),
+            color_support,
 
             // so that the following calls work
             cursor_visible: true,
@@ -25,20 +33,14 @@ impl Default for AnsiBuilder {
 
         ansi_builder
     }
-}
 
-impl AnsiBuilder {
-    pub fn write_str(&mut self, s: &str) {
-        // remove the control characters without having to
-        // push each character separately
-        for part in s.split(|c: char| c.is_control()) {
-            self.s.push_str(part);
-        }
-    }
-
-    pub fn write_char(&mut self, c: char) {
-        if !c.is_control() {
-            self.s.push(c);
+    /// Write a grapheme, or a space in place of a control character so that
+    /// the following cells stay in place.
+    pub fn write_grapheme(&mut self, grapheme: &str) {
+        if grapheme.starts_with(char::is_control) {
+            self.s.push(' ');
+        } else {
+            self.s.push_str(grapheme);
         }
     }
 
@@ -46,36 +48,33 @@ impl AnsiBuilder {
         self.s.push_str("\r\n");
     }
 
+    /// Switch to a style, using as few parameters as possible.
     pub fn write_style(&mut self, style: Style) {
-        macro_rules! sgr {
-            ($($arg:tt)+) => {{
-                write!(self.s, "\x1b[{}m", format_args!($($arg)*)).unwrap();
-            }};
-        }
-
-        if style.fg != self.style.fg {
-            sgr!("3{}", style.fg as u8);
-        }
+        let style = Style {
+            fg: style.fg.downsample(self.color_support),
+            bg: style.bg.downsample(self.color_support),
+            underline_color: style.underline_color.downsample(self.color_support),
+            ..style
+        };
 
-        if style.bg != self.style.bg {
-            sgr!("4{}", style.bg as u8);
+        if style == self.style {
+            return;
         }
 
-        if style.weight != self.style.weight {
-            match style.weight {
-                Weight::Normal => sgr!("22"),
-                Weight::Bold => sgr!("1"),
-                Weight::Dim => sgr!("2"),
-            }
-        }
+        // Sometimes it's shorter to reset everything and start again.
+        let changes = sgr_params(&self.style, &style);
+        let reset = match sgr_params(&Style::default(), &style) {
+            params if params.is_empty() => "0".to_owned(),
+            params => format!("0;{params}"),
+        };
 
-        if style.underline != self.style.underline {
-            match style.underline {
-                true => sgr!("4"),
-                false => sgr!("24"),
-            }
-        }
+        let params = if reset.len() < changes.len() {
+            reset
+        } else {
+            changes
+        };
 
+        write!(self.s, "\x1b[{params}m").unwrap();
         self.style = style;
     }
 
@@ -109,22 +108,144 @@ impl AnsiBuilder {
     }
 }
 
+/// The attributes that can be turned on and off independently, with their
+/// SGR parameters.
+const ATTRIBUTE_PARAMS: [(Attributes, u8, u8); 5] = [
+    (Attributes::ITALIC, 3, 23),
+    (Attributes::BLINK, 5, 25),
+    (Attributes::REVERSE, 7, 27),
+    (Attributes::HIDDEN, 8, 28),
+    (Attributes::STRIKETHROUGH, 9, 29),
+];
+
+/// The SGR parameters to change from one style to another.
+fn sgr_params(from: &Style, to: &Style) -> String {
+    let mut params = String::new();
+    let mut push = |param: fmt::Arguments| {
+        if !params.is_empty() {
+            params.push(';');
+        }
+        params.write_fmt(param).unwrap();
+    };
+
+    if to.fg != from.fg {
+        push(format_args!("{}", ColorSgr(to.fg, Layer::Fg)));
+    }
+
+    if to.bg != from.bg {
+        push(format_args!("{}", ColorSgr(to.bg, Layer::Bg)));
+    }
+
+    if to.weight != from.weight {
+        // Bold and dim are turned off together.
+        if from.weight != Weight::Normal {
+            push(format_args!("22"));
+        }
+
+        match to.weight {
+            Weight::Normal => {}
+            Weight::Bold => push(format_args!("1")),
+            Weight::Dim => push(format_args!("2")),
+        }
+    }
+
+    if to.underline != from.underline {
+        match to.underline {
+            Underline::None => push(format_args!("24")),
+            Underline::Single => push(format_args!("4")),
+            Underline::Double => push(format_args!("4:2")),
+            Underline::Curly => push(format_args!("4:3")),
+            Underline::Dotted => push(format_args!("4:4")),
+            Underline::Dashed => push(format_args!("4:5")),
+        }
+    }
+
+    if to.underline_color != from.underline_color {
+        push(format_args!(
+            "{}",
+            ColorSgr(to.underline_color, Layer::Underline)
+        ));
+    }
+
+    for (attribute, on, off) in ATTRIBUTE_PARAMS {
+        if to.attributes.contains(attribute) != from.attributes.contains(attribute) {
+            match to.attributes.contains(attribute) {
+                true => push(format_args!("{on}")),
+                false => push(format_args!("{off}")),
+            }
+        }
+    }
+
+    params
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Layer {
+    Fg,
+    Bg,
+    Underline,
+}
+
+/// The SGR parameters for a color.
+struct ColorSgr(Color, Layer);
+
+impl fmt::Display for ColorSgr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self(color, layer) = *self;
+
+        let base = match layer {
+            Layer::Fg => 30,
+            Layer::Bg => 40,
+            Layer::Underline => 50,
+        };
+
+        let index = match color {
+            Color::Black => 0,
+            Color::Red => 1,
+            Color::Green => 2,
+            Color::Yellow => 3,
+            Color::Blue => 4,
+            Color::Magenta => 5,
+            Color::Cyan => 6,
+            Color::White => 7,
+            Color::Indexed(index) => index,
+
+            Color::Default => return write!(f, "{}", base + 9),
+            Color::Rgb(r, g, b) => return write!(f, "{};2;{r};{g};{b}", base + 8),
+        };
+
+        match (layer, index) {
+            // there are no shorthands for underline colors
+            (Layer::Underline, _) | (_, 16..) => write!(f, "{};5;{index}", base + 8),
+
+            (_, 0..=7) => write!(f, "{}", base + index),
+            // the bright colors have their own parameters
+            (_, 8..=15) => write!(f, "{}", base + 60 + index - 8),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::AnsiBuilder;
-    use crate::style::{Color, Style, Weight};
+    use crate::style::{Attributes, Color, ColorSupport, Style, Underline, Weight};
+    use unicode_segmentation::UnicodeSegmentation;
 
     #[test]
     fn my_test() {
         let mut ansi = AnsiBuilder::default();
-        ansi.write_str("hello");
+        for g in "hello".graphemes(true) {
+            ansi.write_grapheme(g);
+        }
         ansi.write_newline();
 
         ansi.write_style(Style {
             fg: Color::Magenta,
             ..Default::default()
         });
-        ansi.write_str("world\r\n");
+        for g in "world".graphemes(true) {
+            ansi.write_grapheme(g);
+        }
         ansi.write_newline();
 
         ansi.write_style(Style {
@@ -132,9 +253,88 @@ mod tests {
             weight: Weight::Bold,
             ..Default::default()
         });
-        ansi.write_str("boo!\r\n");
+        for g in "boo!".graphemes(true) {
+            ansi.write_grapheme(g);
+        }
         ansi.write_newline();
 
         print!("{}", ansi.finish());
     }
+
+    #[test]
+    fn colors() {
+        let sgr = |color_support, fg, bg| {
+            let mut ansi = AnsiBuilder::new(color_support);
+            ansi.s.clear();
+            ansi.write_style(Style {
+                fg,
+                bg,
+                ..Default::default()
+            });
+            ansi.s
+        };
+
+        assert_eq!(
+            sgr(
+                ColorSupport::TrueColor,
+                Color::Rgb(1, 2, 3),
+                Color::Indexed(200)
+            ),
+            "\x1b[38;2;1;2;3;48;5;200m"
+        );
+        assert_eq!(
+            sgr(
+                ColorSupport::Indexed256,
+                Color::Rgb(255, 135, 0),
+                Color::Indexed(9)
+            ),
+            "\x1b[38;5;208;101m"
+        );
+        assert_eq!(
+            sgr(
+                ColorSupport::Ansi16,
+                Color::Rgb(255, 255, 255),
+                Color::Green
+            ),
+            "\x1b[97;42m"
+        );
+    }
+
+    #[test]
+    fn transitions() {
+        let mut ansi = AnsiBuilder::default();
+        let mut transition = |style| {
+            ansi.s.clear();
+            ansi.write_style(style);
+            ansi.s.clone()
+        };
+
+        let squiggle = Style {
+            underline: Underline::Curly,
+            underline_color: Color::Red,
+            ..Default::default()
+        };
+        assert_eq!(transition(squiggle), "\x1b[4:3;58;5;1m");
+        assert_eq!(transition(squiggle), "");
+
+        let emphasis = Style {
+            weight: Weight::Dim,
+            attributes: Attributes::ITALIC | Attributes::STRIKETHROUGH,
+            ..squiggle
+        };
+        assert_eq!(transition(emphasis), "\x1b[2;3;9m");
+
+        let bold = Style {
+            weight: Weight::Bold,
+            ..emphasis
+        };
+        assert_eq!(transition(bold), "\x1b[22;1m");
+
+        let reverse = Style {
+            attributes: Attributes::REVERSE,
+            ..Default::default()
+        };
+        assert_eq!(transition(reverse), "\x1b[0;7m");
+        assert_eq!(transition(Style::default()), "\x1b[0m");
+    }
 }
@@ -1,22 +1,127 @@
 use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
 
 use self::ansi_builder::AnsiBuilder;
 use crate::buffer::Buffer;
+use crate::style::ColorSupport;
 
 mod ansi_builder;
 mod sys;
 
+pub(crate) use self::sys::cvt;
+
+/// Written when the terminal is set up: switches to the alternate screen,
+/// enables bracketed paste, mouse and focus reporting, and asks whether the
+/// kitty keyboard protocol is supported.
+const SETUP: &str = "\x1b[?1049h\x1b[?2004h\x1b[?1002h\x1b[?1006h\x1b[?1004h\x1b[?u";
+/// Undoes [`SETUP`], and shows the cursor.
+const RESTORE: &str = "\x1b[?1004l\x1b[?1006l\x1b[?1002l\x1b[?2004l\x1b[?25h\x1b[?1049l";
+
+/// The kitty keyboard protocol flags to enable: disambiguate escape codes,
+/// report event types and report alternate keys.
+const KEYBOARD_FLAGS: u8 = 0b111;
+
+/// Whether the terminal is set up and so needs restoring.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the keyboard flags were pushed and so need popping.
+static KEYBOARD_PROTOCOL: AtomicBool = AtomicBool::new(false);
+
+/// Restore the terminal to how it was before [`Term::new`].
+///
+/// Safe to call more than once, for example from both a panic hook and
+/// [`Term`]'s destructor.
+fn restore() -> io::Result<()> {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    // The alternate screen has its own stack of flags, so pop them before
+    // leaving it.
+    if KEYBOARD_PROTOCOL.swap(false, Ordering::SeqCst) {
+        write!(sys::RawStdout, "\x1b[<u")?;
+    }
+
+    write!(sys::RawStdout, "{RESTORE}")?;
+    sys::restore_termios()
+}
+
+/// Restore the terminal before the panic message is printed, so that it
+/// isn't lost on the alternate screen or mangled by raw mode.
+fn install_panic_hook() {
+    static ONCE: Once = Once::new();
+
+    ONCE.call_once(|| {
+        let prev_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let _ = restore();
+            prev_hook(info);
+        }));
+    });
+}
+
 pub struct Term {
     raw_term: sys::RawTerm,
     raw_stdout: sys::RawStdout,
+
+    /// The last buffer rendered, used to redraw only what has changed.
+    prev_buffer: Option<Buffer>,
+
+    color_support: ColorSupport,
 }
 
 impl Term {
     pub fn new() -> io::Result<Self> {
-        Ok(Self {
+        let mut term = Self {
             raw_term: sys::RawTerm::new()?,
-            raw_stdout: sys::RawStdout::default(),
-        })
+            raw_stdout: sys::RawStdout,
+
+            prev_buffer: None,
+
+            color_support: ColorSupport::from_env(),
+        };
+
+        install_panic_hook();
+        ACTIVE.store(true, Ordering::SeqCst);
+        write!(term.raw_stdout, "{SETUP}")?;
+
+        Ok(term)
+    }
+
+    /// Restore the terminal and stop the process, like Ctrl-Z does in
+    /// other programs. Returns once the process is continued.
+    pub fn suspend(&mut self) -> io::Result<()> {
+        restore()?;
+
+        if unsafe { libc::raise(libc::SIGTSTP) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.resume()
+    }
+
+    /// Set the terminal up again if it was restored, and forget what is on
+    /// screen so that the next frame is drawn in full.
+    pub fn resume(&mut self) -> io::Result<()> {
+        self.prev_buffer = None;
+
+        if !ACTIVE.swap(true, Ordering::SeqCst) {
+            sys::enter_raw_mode()?;
+            write!(self.raw_stdout, "{SETUP}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Enable the kitty keyboard protocol, once the terminal has said that
+    /// it supports it.
+    pub fn enable_keyboard_protocol(&mut self) -> io::Result<()> {
+        if ACTIVE.load(Ordering::SeqCst) && !KEYBOARD_PROTOCOL.swap(true, Ordering::SeqCst) {
+            write!(self.raw_stdout, "\x1b[>{KEYBOARD_FLAGS}u")?;
+        }
+
+        Ok(())
     }
 
     pub fn size(&self) -> io::Result<(usize, usize)> {
@@ -24,30 +129,44 @@ impl Term {
     }
 
     pub fn render_buffer(&mut self, buffer: &Buffer) -> io::Result<()> {
-        self.draw_frame(|ansi_buffer| {
-            for y in 0..buffer.height() {
-                for x in 0..buffer.width() {
-                    let cell = buffer[[x, y]];
+        if let Some(prev_buffer) = &self.prev_buffer {
+            if prev_buffer.width() == buffer.width()
+                && prev_buffer.as_slice() == buffer.as_slice()
+                && prev_buffer.cursor() == buffer.cursor()
+            {
+                return Ok(());
+            }
+        }
 
-                    ansi_buffer.write_style(cell.style);
-                    ansi_buffer.write_char(cell.c);
-                }
+        let prev_buffer = self.prev_buffer.take();
 
-                if buffer.height() == 0 || y < buffer.height() - 1 {
-                    ansi_buffer.write_newline();
+        self.draw_frame(|ansi_buffer| {
+            match &prev_buffer {
+                Some(prev_buffer)
+                    if prev_buffer.width() == buffer.width()
+                        && prev_buffer.height() == buffer.height() =>
+                {
+                    draw_diff(ansi_buffer, prev_buffer, buffer)
                 }
+                _ => draw_full(ansi_buffer, buffer),
             }
 
             if let Some((x, y)) = buffer.cursor() {
                 ansi_buffer.set_cursor_position(x, y);
                 ansi_buffer.show_cursor(true);
             }
-        })
+        })?;
+
+        // Reuse the previous buffer's allocation where possible.
+        let mut prev_buffer = prev_buffer.unwrap_or_else(|| Buffer::new(0, 0));
+        prev_buffer.clone_from(buffer);
+        self.prev_buffer = Some(prev_buffer);
+
+        Ok(())
     }
 
-    fn draw_frame(&mut self, f: impl Fn(&mut AnsiBuilder)) -> io::Result<()> {
-        let mut ansi_buffer = AnsiBuilder::default();
-        ansi_buffer.clear_screen();
+    fn draw_frame(&mut self, f: impl FnOnce(&mut AnsiBuilder)) -> io::Result<()> {
+        let mut ansi_buffer = AnsiBuilder::new(self.color_support);
 
         f(&mut ansi_buffer);
 
@@ -59,22 +178,157 @@ impl Term {
     }
 }
 
+/// The size of the terminal, as `(width, height)`.
+pub fn size() -> io::Result<(usize, usize)> {
+    sys::get_size(libc::STDIN_FILENO)
+}
+
+/// Which of the system's clipboards to set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardKind {
+    Clipboard,
+    /// The X11 primary selection, which is pasted with the middle button.
+    Primary,
+}
+
+/// Ask the terminal to set the system clipboard with OSC 52, which also
+/// works over SSH, and in tmux with `set-clipboard on`. Terminals that
+/// don't support it ignore it, and there is no way to tell.
+pub fn set_clipboard(kind: ClipboardKind, text: &str) -> io::Result<()> {
+    if !ACTIVE.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    write!(sys::RawStdout, "{}", osc52(kind, text))
+}
+
+fn osc52(kind: ClipboardKind, text: &str) -> String {
+    let target = match kind {
+        ClipboardKind::Clipboard => 'c',
+        ClipboardKind::Primary => 'p',
+    };
+    format!("\x1b]52;{target};{}\x07", base64(text.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+
+        // Each byte makes up at least one of the four digits, and the rest
+        // are padding.
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+/// Clear the screen and draw every cell.
+fn draw_full(ansi_buffer: &mut AnsiBuilder, buffer: &Buffer) {
+    ansi_buffer.clear_screen();
+
+    for y in 0..buffer.height() {
+        for x in 0..buffer.width() {
+            let cell = buffer[[x, y]];
+            if cell.symbol.is_continuation() {
+                continue;
+            }
+
+            ansi_buffer.write_style(cell.style);
+            ansi_buffer.write_grapheme(cell.symbol.as_str());
+        }
+
+        if buffer.height() == 0 || y < buffer.height() - 1 {
+            ansi_buffer.write_newline();
+        }
+    }
+}
+
+/// Draw only the cells that differ from the previous buffer, which must
+/// be the same size.
+fn draw_diff(ansi_buffer: &mut AnsiBuilder, prev_buffer: &Buffer, buffer: &Buffer) {
+    // Where the terminal's cursor is, if known.
+    let mut cursor = None;
+
+    for y in 0..buffer.height() {
+        for x in 0..buffer.width() {
+            let cell = buffer[[x, y]];
+            // Continuations change along with the graphemes they follow.
+            if cell == prev_buffer[[x, y]] || cell.symbol.is_continuation() {
+                continue;
+            }
+
+            if cursor != Some((x, y)) {
+                ansi_buffer.set_cursor_position(x, y);
+            }
+
+            ansi_buffer.write_style(cell.style);
+            ansi_buffer.write_grapheme(cell.symbol.as_str());
+
+            // Terminals don't always agree on how wide a grapheme is, so
+            // only trust the cursor to have moved past narrow ones.
+            cursor = (cell.width() == 1).then_some((x + 1, y));
+        }
+    }
+}
+
 impl Drop for Term {
     fn drop(&mut self) {
-        let _ = self.draw_frame(|ansi_buffer| {
-            ansi_buffer.clear_screen();
-            ansi_buffer.show_cursor(true);
-        });
+        let _ = restore();
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Term;
+    use super::{draw_diff, osc52, AnsiBuilder, ClipboardKind, Term};
+    use crate::buffer::{Buffer, Cell};
 
     #[test]
     fn get_term_size() {
         let term = Term::new().unwrap();
         let _ = term.size().unwrap();
     }
+
+    #[test]
+    fn diff_changed_runs() {
+        let prev = Buffer::new(10, 3);
+
+        let mut buffer = prev.clone();
+        buffer[[2, 1]] = Cell::char('a');
+        buffer[[3, 1]] = Cell::char('b');
+        buffer[[9, 2]] = Cell::char('c');
+
+        let mut ansi_buffer = AnsiBuilder::default();
+        draw_diff(&mut ansi_buffer, &prev, &buffer);
+        let ansi = ansi_buffer.finish();
+
+        // the builder starts by homing and hiding the cursor
+        assert_eq!(ansi, "\x1b[1;1H\x1b[?25l\x1b[2;3Hab\x1b[3;10Hc");
+
+        let mut ansi_buffer = AnsiBuilder::default();
+        draw_diff(&mut ansi_buffer, &buffer, &buffer);
+        assert_eq!(ansi_buffer.finish(), "\x1b[1;1H\x1b[?25l");
+    }
+
+    #[test]
+    fn clipboard() {
+        assert_eq!(
+            osc52(ClipboardKind::Clipboard, "hello"),
+            "\x1b]52;c;aGVsbG8=\x07"
+        );
+        assert_eq!(
+            osc52(ClipboardKind::Primary, "héllo!"),
+            "\x1b]52;p;aMOpbGxvIQ==\x07"
+        );
+        assert_eq!(osc52(ClipboardKind::Clipboard, "abc"), "\x1b]52;c;YWJj\x07");
+    }
 }
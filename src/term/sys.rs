@@ -2,51 +2,50 @@ use std::fs::File;
 use std::io::Write;
 use std::mem::ManuallyDrop;
 use std::os::fd::{FromRawFd, RawFd};
+use std::sync::Mutex;
 use std::{io, mem};
 
 use libc::{termios as Termios, winsize as Winsize, STDIN_FILENO, STDOUT_FILENO};
 
-macro_rules! cvt {
-    ($res:expr) => {{
-        match $res {
-            -1 => Err(io::Error::last_os_error()),
-            res => Ok(res),
-        }
-    }};
+/// Turn the -1 that libc functions return on failure into the error in
+/// `errno`.
+pub(crate) fn cvt<T: PartialEq + From<i8>>(res: T) -> io::Result<T> {
+    match res == T::from(-1) {
+        true => Err(io::Error::last_os_error()),
+        false => Ok(res),
+    }
 }
 
 fn get_termios(fd: RawFd) -> io::Result<Termios> {
     unsafe {
         let mut termios: Termios = mem::zeroed();
-        cvt!(libc::tcgetattr(fd, &mut termios))?;
+        cvt(libc::tcgetattr(fd, &mut termios))?;
         Ok(termios)
     }
 }
 
 fn set_termios(fd: RawFd, termios: &Termios) -> io::Result<()> {
-    cvt!(unsafe { libc::tcsetattr(fd, libc::TCSANOW, termios) })?;
+    cvt(unsafe { libc::tcsetattr(fd, libc::TCSANOW, termios) })?;
     Ok(())
 }
 
-fn get_size(fd: RawFd) -> io::Result<(usize, usize)> {
+pub(super) fn get_size(fd: RawFd) -> io::Result<(usize, usize)> {
     let mut size: Winsize = unsafe { mem::zeroed() };
-    cvt!(unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) })?;
+    cvt(unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) })?;
     Ok((size.ws_col as usize, size.ws_row as usize))
 }
 
-pub(super) struct RawTerm {
-    termios_prev: Termios,
-}
+/// The terminal settings from before entering raw mode.
+///
+/// Kept globally so that they can be restored from a panic hook.
+static TERMIOS_PREV: Mutex<Option<Termios>> = Mutex::new(None);
+
+pub(super) struct RawTerm(());
 
 impl RawTerm {
     pub fn new() -> io::Result<Self> {
-        let mut termios = get_termios(STDIN_FILENO)?;
-        let termios_prev = termios;
-
-        unsafe { libc::cfmakeraw(&mut termios as *mut Termios) };
-        set_termios(STDIN_FILENO, &termios)?;
-
-        Ok(Self { termios_prev })
+        enter_raw_mode()?;
+        Ok(Self(()))
     }
 
     pub fn get_size(&self) -> io::Result<(usize, usize)> {
@@ -56,7 +55,28 @@ impl RawTerm {
 
 impl Drop for RawTerm {
     fn drop(&mut self) {
-        let _ = set_termios(STDIN_FILENO, &self.termios_prev);
+        let _ = restore_termios();
+    }
+}
+
+/// Switch to raw mode, saving the current settings.
+pub(super) fn enter_raw_mode() -> io::Result<()> {
+    let mut termios = get_termios(STDIN_FILENO)?;
+    *TERMIOS_PREV.lock().unwrap_or_else(|err| err.into_inner()) = Some(termios);
+
+    unsafe { libc::cfmakeraw(&mut termios as *mut Termios) };
+    set_termios(STDIN_FILENO, &termios)
+}
+
+/// Restore the terminal settings from before entering raw mode.
+pub(super) fn restore_termios() -> io::Result<()> {
+    let termios_prev = TERMIOS_PREV
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .take();
+    match termios_prev {
+        Some(termios_prev) => set_termios(STDIN_FILENO, &termios_prev),
+        None => Ok(()),
     }
 }
 
@@ -6,7 +6,7 @@ use std::time::{Duration, Instant};
 use log::trace;
 
 use crate::buffer::Buffer;
-use crate::event::{Event, EventReader};
+use crate::event::{Event, EventKind, EventReader};
 use crate::term::Term;
 
 #[must_use]
@@ -15,15 +15,68 @@ pub enum ControlFlow {
     #[default]
     Continue,
 
+    /// Stop the process until it is continued, like Ctrl-Z.
+    Suspend,
+
     Exit,
 }
 
+/// The outcome of updating a widget.
+#[must_use]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Update {
+    pub control_flow: ControlFlow,
+
+    /// The widget has changed and needs to be redrawn.
+    pub redraw: bool,
+
+    /// Update again after this long, even if no events arrive.
+    /// Used by widgets that animate.
+    pub next_tick: Option<Duration>,
+}
+
+impl Update {
+    pub fn exit() -> Self {
+        Self {
+            control_flow: ControlFlow::Exit,
+            ..Default::default()
+        }
+    }
+
+    pub fn redraw() -> Self {
+        Self {
+            redraw: true,
+            ..Default::default()
+        }
+    }
+
+    /// Combine the updates of two widgets.
+    pub fn merge(self, other: Self) -> Self {
+        let control_flow = match (self.control_flow, other.control_flow) {
+            (ControlFlow::Exit, _) | (_, ControlFlow::Exit) => ControlFlow::Exit,
+            (ControlFlow::Suspend, _) | (_, ControlFlow::Suspend) => ControlFlow::Suspend,
+            _ => ControlFlow::Continue,
+        };
+
+        let next_tick = match (self.next_tick, other.next_tick) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
+        Self {
+            control_flow,
+            redraw: self.redraw || other.redraw,
+            next_tick,
+        }
+    }
+}
+
 pub trait Widget<GlobalState> {
     fn handle_event(&mut self, state: &mut GlobalState, event: &Event) -> Option<ControlFlow>;
 
-    fn update(&mut self, state: &mut GlobalState) -> ControlFlow;
+    fn update(&mut self, state: &mut GlobalState) -> Update;
 
-    fn render(&mut self, buf: &mut Buffer);
+    fn render(&mut self, state: &GlobalState, buf: &mut Buffer);
 }
 
 pub struct App<GlobalState> {
@@ -31,78 +84,116 @@ pub struct App<GlobalState> {
     root_buf: Buffer,
 
     term: Term,
+    term_size: (usize, usize),
     events: EventReader,
 
     state: GlobalState,
-
-    refresh_rate: Duration,
 }
 
 impl<GlobalState> App<GlobalState> {
-    pub fn new(
-        state: GlobalState,
-        widget: impl Widget<GlobalState> + 'static,
-        refresh_rate: Duration,
-    ) -> io::Result<Self> {
+    pub fn new(state: GlobalState, widget: impl Widget<GlobalState> + 'static) -> io::Result<Self> {
         let term = Term::new()?;
         let term_size = term.size()?;
 
         Ok(Self {
             root: Box::new(widget),
-            root_buf: Buffer::new(term_size.0, term_size.0),
+            root_buf: Buffer::new(term_size.0, term_size.1),
 
             term,
-            events: EventReader::new(),
+            term_size,
+            events: EventReader::new()?,
 
             state,
-
-            refresh_rate,
         })
     }
 
+    /// Set how long to wait for the rest of an escape sequence before
+    /// treating it as the escape key.
+    pub fn set_escape_timeout(&mut self, timeout: Duration) {
+        self.events.set_escape_timeout(timeout);
+    }
+
     pub fn run(mut self) -> io::Result<()> {
-        let mut last_time = Instant::now();
+        // Always draw the first frame.
+        let mut redraw = true;
 
         loop {
-            let time = Instant::now();
-            let deadline = time
-                .checked_add(self.refresh_rate)
-                .expect("deadline overflowed");
-
-            if let ControlFlow::Exit = self.root.update(&mut self.state) {
-                break;
+            let update = self.root.update(&mut self.state);
+            match update.control_flow {
+                ControlFlow::Continue => {}
+                ControlFlow::Suspend => {
+                    self.term.suspend()?;
+                    redraw = true;
+                }
+                ControlFlow::Exit => break,
             }
 
-            if let ControlFlow::Exit = self.handle_events(deadline)? {
-                break;
+            if redraw || update.redraw {
+                let time = Instant::now();
+                self.render()?;
+                trace!("frame finished in {:?}", time.elapsed());
             }
 
-            self.render()?;
+            let deadline = update.next_tick.map(|next_tick| Instant::now() + next_tick);
 
-            let duration = time.duration_since(last_time);
-            last_time = time;
-
-            trace!("frame finished in {duration:?}");
+            match self.handle_events(deadline)? {
+                Some(ControlFlow::Continue) => redraw = true,
+                Some(ControlFlow::Suspend) => {
+                    self.term.suspend()?;
+                    redraw = true;
+                }
+                Some(ControlFlow::Exit) => break,
+                None => redraw = false,
+            }
         }
 
         Ok(())
     }
 
-    fn handle_events(&mut self, deadline: Instant) -> io::Result<ControlFlow> {
+    /// Wait for events until the deadline, then handle any others that are
+    /// immediately available. Returns `None` if nothing needs redrawing.
+    fn handle_events(&mut self, deadline: Option<Instant>) -> io::Result<Option<ControlFlow>> {
+        let mut handled = None;
+
+        let mut deadline = deadline;
         while let Some(event) = self.events.read_with_deadline(deadline)? {
-            if let Some(ControlFlow::Exit) = self.root.handle_event(&mut self.state, &event) {
-                return Ok(ControlFlow::Exit);
+            match event.kind {
+                EventKind::Resize { width, height } if (width, height) != self.term_size => {
+                    self.term_size = (width, height);
+                    handled = Some(ControlFlow::Continue);
+                }
+
+                // Exit normally so that the terminal is restored.
+                EventKind::Terminate => return Ok(Some(ControlFlow::Exit)),
+
+                EventKind::KeyboardProtocol => self.term.enable_keyboard_protocol()?,
+
+                // The screen may have been drawn over while stopped.
+                EventKind::Resume => {
+                    self.term.resume()?;
+                    handled = Some(ControlFlow::Continue);
+                }
+
+                _ => {}
+            }
+
+            match self.root.handle_event(&mut self.state, &event) {
+                Some(ControlFlow::Continue) => handled = Some(ControlFlow::Continue),
+                Some(control_flow) => return Ok(Some(control_flow)),
+                None => {}
             }
+
+            deadline = Some(Instant::now());
         }
 
-        Ok(ControlFlow::Continue)
+        Ok(handled)
     }
 
     fn render(&mut self) -> io::Result<()> {
-        let term_size = self.term.size()?;
-        self.root_buf.resize_and_clear(term_size.0, term_size.1);
+        self.root_buf
+            .resize_and_clear(self.term_size.0, self.term_size.1);
 
-        self.root.render(&mut self.root_buf);
+        self.root.render(&self.state, &mut self.root_buf);
         self.term.render_buffer(&self.root_buf)?;
 
         Ok(())
@@ -1,34 +1,56 @@
+use std::time::{Duration, Instant};
+
 use crate::event::Event;
 use crate::ui::*;
 
-#[derive(Default)]
 pub struct InputPrinter {
-    ticks: usize,
+    start: Instant,
+    secs: u64,
     event: Option<Event>,
 }
 
+impl Default for InputPrinter {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            secs: 0,
+            event: None,
+        }
+    }
+}
+
 impl Widget<()> for InputPrinter {
     fn handle_event(&mut self, _state: &mut (), event: &Event) -> Option<ControlFlow> {
         self.event = Some(event.clone());
         Some(ControlFlow::Continue)
     }
 
-    fn update(&mut self, _state: &mut ()) -> ControlFlow {
-        self.ticks += 1;
-        ControlFlow::Continue
+    fn update(&mut self, _state: &mut ()) -> Update {
+        let elapsed = self.start.elapsed();
+        let secs = elapsed.as_secs();
+
+        let redraw = secs != self.secs;
+        self.secs = secs;
+
+        // Tick again when the displayed time changes.
+        let next_second = Duration::from_secs(secs + 1);
+
+        Update {
+            redraw,
+            next_tick: Some(next_second - elapsed),
+            ..Default::default()
+        }
     }
 
-    fn render(&mut self, buf: &mut crate::buffer::Buffer) {
+    fn render(&mut self, _state: &(), buf: &mut crate::buffer::Buffer) {
         if buf.height() == 0 {
             return;
         }
 
-        let s = format!("Time: {}\nEvent: {:#?}", self.ticks / 60, self.event);
+        let s = format!("Time: {}\nEvent: {:#?}", self.secs, self.event);
 
         for (i, line) in s.lines().enumerate().take(buf.height()) {
-            for (x, c) in line.chars().enumerate().take(buf.width()) {
-                buf[[x, i]].c = c;
-            }
+            buf.set_str(0, i, line);
         }
     }
 }